@@ -1,7 +1,9 @@
 .text:
 	global print_char
 	global print_int
+	global print_float
 	global print_string
+	global print_bool
 
 ; rdi - char c
 print_char:
@@ -14,29 +16,201 @@ print_char:
 	pop rdi
 	ret
 
-; edi - i32 i
+; rdi - i64 i
 print_int:
-	mov r12, 0
-	mov eax, edi
+	mov rax, rdi
+	cmp rax, 0
+	jge print_int1
+	push rax
+	mov rdi, '-'
+	call print_char
+	pop rax
+	neg rax ; read as unsigned this is right even for i64::MIN
+
 print_int1:
-	mov edx, 0
-	mov ecx, 10
-	div ecx ; eax /= ecx
+	mov rdi, rax
+	mov rsi, 1
+	jmp print_digits
+
+; rdi - u64 n
+; rsi - minimum number of digits, padded with leading zeros
+print_digits:
+	push r12
+	mov rax, rdi
+	mov r12, 0
+print_digits1:
+	xor rdx, rdx
+	mov rcx, 10
+	div rcx ; unsigned rax /= 10
 
 	push rdx
 	inc r12
 
-	cmp eax, 0
-	jg print_int1 ; push chars on stack until int is 0
+	cmp rax, 0
+	jne print_digits1 ; push digits on stack until the number is 0
+	cmp r12, rsi
+	jb print_digits1 ; and then zeros until there are enough
 
-print_int2:
+print_digits2:
 	pop rdi
-	add edi, 0x30 ; convert to char
+	add rdi, '0' ; convert to char
 	call print_char
 	dec r12
+	jnz print_digits2 ; print all digits on stack
 
-	cmp r12, 0
-	jg print_int2 ; print all chars on stack
+	pop r12
+	ret
+
+; xmm0 - f64 f
+; printed with up to 6 decimal places, or as nan, inf or -inf
+print_float:
+	push r13
+	push r14
+
+	ucomisd xmm0, xmm0
+	jp print_float_nan ; only nan is unordered with itself
+
+	movq rax, xmm0
+	btr rax, 63 ; clear the sign bit
+	jnc print_float1
+	push rax
+	mov rdi, '-'
+	call print_char
+	pop rax
+
+print_float1:
+	; positive doubles are ordered like their bit patterns
+	mov rcx, 0x7ff0000000000000
+	cmp rax, rcx
+	je print_float_inf
+	mov rcx, 0x43e0000000000000 ; 2^63
+	cmp rax, rcx
+	jae print_float_big
+
+	; below 2^63 the integer part fits in a register, and the fraction is exact
+	movq xmm0, rax
+	cvttsd2si r13, xmm0
+	cvtsi2sd xmm1, r13
+	subsd xmm0, xmm1
+	mov rax, 1000000
+	cvtsi2sd xmm1, rax
+	mulsd xmm0, xmm1
+	cvtsd2si r14, xmm0 ; rounded to millionths
+	cmp r14, 1000000
+	jb print_float2
+	inc r13 ; the fraction rounded up to a whole
+	mov r14, 0
+
+print_float2:
+	mov rdi, r13
+	mov rsi, 1
+	call print_digits
+	mov rdi, '.'
+	call print_char
+
+	mov r13, r14
+	mov r14, 6 ; fraction digits
+print_float3:
+	cmp r14, 1
+	je print_float4
+	mov rax, r13
+	xor rdx, rdx
+	mov rcx, 10
+	div rcx
+	cmp rdx, 0
+	jne print_float4 ; drop trailing zeros, keeping at least one digit
+	mov r13, rax
+	dec r14
+	jmp print_float3
+
+print_float4:
+	mov rdi, r13
+	mov rsi, r14
+	call print_digits
+	jmp print_float_end
+
+print_float_big:
+	; from 2^63 on doubles are whole numbers, the 53 bit mantissa shifted left by 11
+	; to 971 bits, so they are written out into 17 qwords on the stack, least
+	; significant first, and converted 18 decimal digits at a time
+	mov r8, rax
+	sub rsp, 136
+	mov r13, rsp
+	mov rcx, 16
+print_float_big1:
+	mov qword [r13 + rcx*8], 0
+	dec rcx
+	jns print_float_big1
+
+	mov rcx, r8
+	shr rcx, 52
+	sub rcx, 1075 ; the shift, from the biased exponent
+	mov rax, 0x000fffffffffffff
+	and rax, r8
+	bts rax, 52 ; the implicit leading bit
+	mov r9, rcx
+	shr r9, 6 ; qword the mantissa starts in
+	and rcx, 63
+	xor rdx, rdx
+	shld rdx, rax, cl ; the part spilling into the next qword
+	shl rax, cl
+	mov [r13 + r9*8], rax
+	mov [r13 + r9*8 + 8], rdx
+
+	mov r14, 0
+	mov r9, 1000000000000000000
+print_float_big2:
+	xor rdx, rdx
+	mov rcx, 16
+print_float_big3:
+	mov rax, [r13 + rcx*8]
+	div r9 ; the remainder carries into the next qword down
+	mov [r13 + rcx*8], rax
+	dec rcx
+	jns print_float_big3
+
+	push rdx
+	inc r14
+	mov rcx, 16
+print_float_big4:
+	cmp qword [r13 + rcx*8], 0
+	jne print_float_big2 ; push chunks on stack until the number is 0
+	dec rcx
+	jns print_float_big4
+
+	pop rdi
+	mov rsi, 1
+	call print_digits
+print_float_big5:
+	dec r14
+	jz print_float_big6
+	pop rdi
+	mov rsi, 18
+	call print_digits
+	jmp print_float_big5 ; print all chunks on stack, the later ones padded
+
+print_float_big6:
+	add rsp, 136
+	mov rdi, '.'
+	call print_char
+	mov rdi, '0'
+	call print_char
+	jmp print_float_end
+
+print_float_nan:
+	mov rsi, nan_string
+	mov rdx, 3
+	call print_string
+	jmp print_float_end
+
+print_float_inf:
+	mov rsi, inf_string
+	mov rdx, 3
+	call print_string
+
+print_float_end:
+	pop r14
+	pop r13
 	ret
 
 ; rsi - char* message
@@ -45,4 +219,20 @@ print_string:
 	mov rax, 1 ; sys_write
 	mov rdi, 1 ; stdout
 	syscall
-	ret
\ No newline at end of file
+	ret
+
+; rdi - bool b
+print_bool:
+	mov rsi, true_string
+	mov rdx, 4
+	cmp rdi, 0
+	jne print_string
+	mov rsi, false_string
+	mov rdx, 5
+	jmp print_string
+
+SECTION .rodata
+true_string: db "true"
+false_string: db "false"
+nan_string: db "nan"
+inf_string: db "inf"
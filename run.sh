@@ -1,3 +1,3 @@
 #! /bin/sh
 
-cargo run "$@"
\ No newline at end of file
+cargo run -- run "$@"
@@ -0,0 +1,90 @@
+use std::fmt;
+
+// A line of the .text section.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Line {
+    // written out as is, like `extern` and `global`
+    Directive(String),
+    Label(String),
+    Instruction(&'static str, Vec<Operand>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operand {
+    Register(&'static str),
+    // a number or a label
+    Immediate(String),
+    // the QWORD at an address like `rbp-8`
+    Memory(String),
+}
+
+pub fn reg(name: &'static str) -> Operand {
+    Operand::Register(name)
+}
+
+pub fn imm(value: impl ToString) -> Operand {
+    Operand::Immediate(value.to_string())
+}
+
+pub fn mem(address: impl ToString) -> Operand {
+    Operand::Memory(address.to_string())
+}
+
+impl Operand {
+    pub fn is_memory(&self) -> bool {
+        matches!(self, Operand::Memory(_))
+    }
+
+    pub fn is_immediate(&self) -> bool {
+        matches!(self, Operand::Immediate(_))
+    }
+
+    // Whether the operand reads or writes any part of the register, as a whole or
+    // in an address.
+    pub fn uses(&self, register: &str) -> bool {
+        let register = full_register(register);
+        match self {
+            Operand::Register(name) => full_register(name) == register,
+            Operand::Immediate(_) => false,
+            Operand::Memory(address) => address
+                .split(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                .any(|part| full_register(part) == register),
+        }
+    }
+}
+
+// The 64 bit register the name is part of, only for the partial registers codegen uses.
+fn full_register(name: &str) -> &str {
+    match name {
+        "eax" | "ax" | "al" => "rax",
+        "ecx" | "cx" | "cl" => "rcx",
+        _ => name,
+    }
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operand::Register(name) => write!(f, "{}", name),
+            Operand::Immediate(value) => write!(f, "{}", value),
+            Operand::Memory(address) => write!(f, "QWORD [{}]", address),
+        }
+    }
+}
+
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Line::Directive(directive) => write!(f, "\t{}", directive),
+            Line::Label(label) => write!(f, "\t{}:", label),
+            Line::Instruction(mnemonic, operands) => {
+                write!(f, "\t{}", mnemonic)?;
+                for (i, operand) in operands.iter().enumerate() {
+                    let separator = if i == 0 { " " } else { ", " };
+                    write!(f, "{}{}", separator, operand)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
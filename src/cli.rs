@@ -0,0 +1,143 @@
+use std::process;
+
+// Syntax errors reported before giving up, unless overridden with --max-errors
+const DEFAULT_MAX_ERRORS: usize = 20;
+
+const USAGE: &str = "\
+usage: jpl <command> [options] <file>
+
+commands:
+    build    compile a program into an executable
+    run      compile and run a program, passing it any arguments after `--`
+    check    check a program for errors without compiling it
+    emit     print a stage of compilation: --tokens, --ast, --ir or --asm
+
+options:
+    -o <file>           name of the executable to build (build defaults to a.out)
+    --save-temps        keep the intermediate assembly and object files
+    --no-peephole       emit the assembly without the peephole optimizations
+    --max-errors <n>    stop after n syntax errors (default 20)";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Command {
+    Build,
+    Run,
+    Check,
+    Emit(Stage),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Stage {
+    Tokens,
+    Ast,
+    Ir,
+    Asm,
+}
+
+#[derive(Debug)]
+pub struct Options {
+    pub command: Command,
+    pub path: String,
+    // where the executable goes, `jpl run` builds into the build directory by default
+    pub output: Option<String>,
+    pub save_temps: bool,
+    // off with --no-peephole, to see the assembly as the backend emits it
+    pub peephole: bool,
+    pub max_errors: usize,
+    // passed to the program by `jpl run`
+    pub program_args: Vec<String>,
+}
+
+// Exits with the usage message if the arguments don't make sense.
+pub fn parse_args(mut args: impl Iterator<Item = String>) -> Options {
+    // None for emit, which is completed by its stage option
+    let command = match args.next().as_deref() {
+        Some("build") => Some(Command::Build),
+        Some("run") => Some(Command::Run),
+        Some("check") => Some(Command::Check),
+        Some("emit") => None,
+        Some(command) => usage_error(&format!("unknown command '{}'", command)),
+        None => usage_error("no command given"),
+    };
+
+    let mut stage = None;
+    let mut path = None;
+    let mut output = None;
+    let mut save_temps = false;
+    let mut peephole = true;
+    let mut max_errors = DEFAULT_MAX_ERRORS;
+    let mut program_args = vec![];
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" => match args.next() {
+                Some(file) => output = Some(file),
+                None => usage_error("-o expects a file name"),
+            },
+            "--tokens" | "--ast" | "--ir" | "--asm" => {
+                let new_stage = match arg.as_str() {
+                    "--tokens" => Stage::Tokens,
+                    "--ast" => Stage::Ast,
+                    "--ir" => Stage::Ir,
+                    _ => Stage::Asm,
+                };
+                if stage.replace(new_stage).is_some() {
+                    usage_error("emit expects only one of --tokens, --ast, --ir or --asm");
+                }
+            }
+            "--save-temps" => save_temps = true,
+            "--no-peephole" => peephole = false,
+            "--max-errors" => {
+                max_errors = match args.next().and_then(|n| n.parse().ok()) {
+                    Some(n) if n > 0 => n,
+                    _ => usage_error("--max-errors expects a positive number"),
+                }
+            }
+            "--" if command == Some(Command::Run) => {
+                program_args = args.by_ref().collect();
+            }
+            _ if arg.starts_with('-') => usage_error(&format!("unknown option '{}'", arg)),
+            _ if path.is_some() => usage_error("only one input file is supported"),
+            _ => path = Some(arg),
+        }
+    }
+
+    let command = match (command, stage) {
+        (Some(command), None) => command,
+        (Some(_), Some(_)) => usage_error("--tokens, --ast, --ir and --asm are only used by emit"),
+        (None, Some(stage)) => Command::Emit(stage),
+        (None, None) => usage_error("emit expects one of --tokens, --ast, --ir or --asm"),
+    };
+
+    let path = match path {
+        Some(path) => path,
+        None => usage_error("no input file"),
+    };
+
+    if (output.is_some() || save_temps) && !matches!(command, Command::Build | Command::Run) {
+        usage_error("-o and --save-temps are only used by build and run");
+    }
+    if !peephole
+        && !matches!(
+            command,
+            Command::Build | Command::Run | Command::Emit(Stage::Asm)
+        )
+    {
+        usage_error("--no-peephole is only used when generating assembly");
+    }
+
+    Options {
+        command,
+        path,
+        output,
+        save_temps,
+        peephole,
+        max_errors,
+        program_args,
+    }
+}
+
+fn usage_error(message: &str) -> ! {
+    eprintln!("fatal error: {}\n\n{}", message, USAGE);
+    process::exit(1);
+}
@@ -1,24 +1,56 @@
-use std::{fs::File, io::Write, process::Command};
+use std::collections::HashMap;
 
-use crate::parser::{BinaryOperator, ParsedExpr, ParsedStatement};
+use crate::{
+    asm::{imm, mem, reg, Line, Operand},
+    ir::{BlockId, Constant, Function, Instruction, Program, Reg, Terminator},
+    parser::{BinaryOperator, UnaryOperator},
+    peephole, regalloc,
+    symbols::VarId,
+    typechecker::{CheckedVariable, Type},
+};
 
-struct Emitter {
+// System V integer argument registers, in order
+const ARG_REGISTERS: [&str; 6] = ["rdi", "rsi", "rdx", "rcx", "r8", "r9"];
+
+struct Emitter<'a> {
     output_data: String,
-    output_text: String,
+    output_text: Vec<Line>,
+    label_count: usize,
+    variables: &'a [CheckedVariable],
+    // .data labels of the string literals emitted so far
+    strings: HashMap<String, String>,
+    // where the current function's registers live, and the callee saved registers it
+    // has to restore
+    locations: HashMap<Reg, Operand>,
+    saved: Vec<(&'static str, Operand)>,
+    // the label of each block of the current function
+    block_labels: Vec<String>,
 }
 
-impl Emitter {
-    fn new() -> Self {
+impl<'a> Emitter<'a> {
+    fn new(variables: &'a [CheckedVariable]) -> Self {
         let mut emitter = Self {
             output_data: String::from("SECTION .data\n"),
-            output_text: String::from("SECTION .text\n"),
+            output_text: vec![],
+            label_count: 0,
+            variables,
+            strings: HashMap::new(),
+            locations: HashMap::new(),
+            saved: vec![],
+            block_labels: vec![],
         };
 
-        emitter.emit_textln("extern print_int");
-        emitter.emit_textln("extern print_char");
-        emitter.emit_textln("extern print_string");
-        emitter.emit_textln("global _start");
-        emitter.emit_textln("_start:");
+        for routine in [
+            "print_int",
+            "print_float",
+            "print_char",
+            "print_string",
+            "print_bool",
+        ] {
+            emitter.emit_directive(format!("extern {}", routine));
+        }
+        emitter.emit_directive("global _start".to_string());
+        emitter.emit_label("_start".to_string());
 
         emitter
     }
@@ -27,174 +59,557 @@ impl Emitter {
         self.output_data.push_str(&format!("\t{}\n", asm));
     }
 
-    fn emit_textln(&mut self, asm: &str) {
-        self.output_text.push_str(&format!("\t{}\n", asm));
+    fn emit(&mut self, mnemonic: &'static str, operands: Vec<Operand>) {
+        self.output_text.push(Line::Instruction(mnemonic, operands));
+    }
+
+    fn emit_directive(&mut self, directive: String) {
+        self.output_text.push(Line::Directive(directive));
+    }
+
+    fn new_label(&mut self) -> String {
+        self.label_count += 1;
+        format!(".L{}", self.label_count)
+    }
+
+    fn emit_label(&mut self, label: String) {
+        self.output_text.push(Line::Label(label));
+    }
+
+    // Returns the label of a string literal, emitting it into .data the first time it is used.
+    fn string_label(&mut self, string: &str) -> String {
+        if let Some(label) = self.strings.get(string) {
+            return label.clone();
+        }
+
+        let label = format!("str_{}", self.strings.len());
+        self.emit_dataln(&format!("{} dq {}", label, string.len()));
+        if !string.is_empty() {
+            self.emit_dataln(&format!("db {}", string_bytes(string)));
+        }
+
+        self.strings.insert(string.to_string(), label.clone());
+        label
+    }
+
+    // The value of a constant as an immediate or a label.
+    fn constant_operand(&mut self, constant: &Constant) -> String {
+        match constant {
+            Constant::Int(i) => i.to_string(),
+            Constant::Float(f) => float_bits(*f),
+            Constant::Bool(b) => (*b as i64).to_string(),
+            Constant::String(s) => self.string_label(s),
+        }
+    }
+
+    // Globals initialized with a constant need no code, the rest start out as 0.
+    fn emit_globals(&mut self, program: &Program) {
+        for (id, variable) in program.variables.iter().enumerate() {
+            if variable.global && variable.used {
+                let initial = match program.initializers.get(&id) {
+                    Some(constant) => self.constant_operand(constant),
+                    None => "0".to_string(),
+                };
+                // global names are unique, as redeclaring a variable in the same scope is an error
+                self.emit_dataln(&format!("var_{} dq {}", variable.name, initial));
+            }
+        }
+    }
+
+    fn global(&self, id: VarId) -> Operand {
+        mem(format!("var_{}", self.variables[id].name))
+    }
+
+    fn register(&self, reg: Reg) -> Operand {
+        self.locations[&reg].clone()
+    }
+
+    fn block_label(&self, id: BlockId) -> Operand {
+        imm(&self.block_labels[id])
+    }
+
+    // x86 has no memory to memory mov, so those go through rax.
+    fn emit_move(&mut self, dst: Operand, src: Operand) {
+        if dst == src {
+            return;
+        }
+
+        if dst.is_memory() && src.is_memory() {
+            self.emit("mov", vec![reg("rax"), src]);
+            self.emit("mov", vec![dst, reg("rax")]);
+        } else {
+            self.emit("mov", vec![dst, src]);
+        }
+    }
+
+    fn begin_function(&mut self, function: &Function) {
+        let allocation = regalloc::allocate(function);
+        self.locations = allocation.locations;
+        self.saved = allocation.saved;
+        self.block_labels = function.blocks.iter().map(|_| self.new_label()).collect();
+
+        // functions are entered with rsp 8 bytes past a 16 byte boundary, because of the
+        // return address, and pushing rbp aligns it again. _start is entered aligned and
+        // never returns, so it doesn't save rbp.
+        if function.return_type.is_some() {
+            self.emit("push", vec![reg("rbp")]);
+        }
+        self.emit("mov", vec![reg("rbp"), reg("rsp")]);
+        if allocation.frame_size > 0 {
+            let size = allocation.frame_size.div_ceil(16) * 16;
+            self.emit("sub", vec![reg("rsp"), imm(size)]);
+        }
+        for (register, slot) in self.saved.clone() {
+            self.emit("mov", vec![slot, reg(register)]);
+        }
     }
 
-    fn emit_expr(&mut self, expr: &ParsedExpr) {
-        match expr {
-            ParsedExpr::IntegerConstant(i) => {
-                self.emit_textln(&format!("push {}", i));
+    fn emit_function(&mut self, function: &Function) {
+        self.emit_label(format!("fn_{}", function.name));
+        self.begin_function(function);
+
+        // floats are passed by bit pattern in the integer registers too
+        for (i, param) in function.params.iter().enumerate() {
+            let location = self.register(*param);
+            match ARG_REGISTERS.get(i) {
+                Some(register) => self.emit("mov", vec![location, reg(register)]),
+                None => {
+                    // the rest are on the stack, above the saved rbp and return address
+                    let offset = 16 + 8 * (i - ARG_REGISTERS.len());
+                    self.emit_move(location, mem(format!("rbp+{}", offset)));
+                }
             }
-            ParsedExpr::FloatConstant(_) => todo!(),
-            ParsedExpr::BinaryOp(expr1, op, expr2) => {
-                self.emit_expr(&*expr1);
-                self.emit_expr(&*expr2);
-                match op {
-                    BinaryOperator::Add => {
-                        self.emit_textln("pop rax");
-                        self.emit_textln("pop rbx");
-                        self.emit_textln("add rax, rbx");
-                        self.emit_textln("push rax");
+        }
+
+        self.emit_blocks(function);
+    }
+
+    fn emit_blocks(&mut self, function: &Function) {
+        for (id, block) in function.blocks.iter().enumerate() {
+            if id > 0 {
+                self.emit_label(self.block_labels[id].clone());
+            }
+
+            let fused = fused_comparison(function, id);
+            let instructions = match fused {
+                Some(_) => &block.instructions[..block.instructions.len() - 1],
+                None => &block.instructions[..],
+            };
+            for instruction in instructions {
+                self.emit_instruction(function, instruction);
+            }
+
+            let next = id + 1;
+            match (&block.terminator, fused) {
+                (Terminator::Branch(_, then_id, else_id), Some((op, lhs, rhs))) => {
+                    let (lhs, rhs) = (self.register(lhs), self.register(rhs));
+                    // cmp can't take an immediate first or two memory operands
+                    if lhs.is_immediate() || (lhs.is_memory() && rhs.is_memory()) {
+                        self.emit("mov", vec![reg("rax"), lhs]);
+                        self.emit("cmp", vec![reg("rax"), rhs]);
+                    } else {
+                        self.emit("cmp", vec![lhs, rhs]);
+                    }
+                    let jump = match op {
+                        BinaryOperator::Equal => "je",
+                        BinaryOperator::NotEqual => "jne",
+                        BinaryOperator::LessThan => "jl",
+                        BinaryOperator::LessThanOrEqual => "jle",
+                        BinaryOperator::GreaterThan => "jg",
+                        _ => "jge",
+                    };
+                    self.emit_branch(jump, *then_id, *else_id, next);
+                }
+                (Terminator::Branch(condition, then_id, else_id), None) => {
+                    let condition = self.register(*condition);
+                    if condition.is_immediate() {
+                        // a constant condition, like that of `while true`
+                        let target = if condition == imm(0) {
+                            else_id
+                        } else {
+                            then_id
+                        };
+                        if *target != next {
+                            self.emit("jmp", vec![self.block_label(*target)]);
+                        }
+                    } else {
+                        self.emit("cmp", vec![condition, imm(0)]);
+                        self.emit_branch("jne", *then_id, *else_id, next);
                     }
-                    BinaryOperator::Subtract => {
-                        self.emit_textln("pop rax");
-                        self.emit_textln("pop rbx");
-                        self.emit_textln("sub rbx, rax");
-                        self.emit_textln("push rbx");
-                    },
-                    BinaryOperator::Multiply => {
-                        self.emit_textln("pop rax");
-                        self.emit_textln("pop rbx");
-                        self.emit_textln("imul rax, rbx");
-                        self.emit_textln("push rax");
-                    },
-                    BinaryOperator::Divide => {
-                        self.emit_textln("pop rbx");
-                        self.emit_textln("pop rax");
-                        self.emit_textln("xor rdx, rdx");
-                        self.emit_textln("idiv rbx");
-                        self.emit_textln("push rax");
-                    },
                 }
+                (Terminator::Jump(target), _) => {
+                    if *target != next {
+                        self.emit("jmp", vec![self.block_label(*target)]);
+                    }
+                }
+                (Terminator::Return(Some(value)), _) => {
+                    self.emit("mov", vec![reg("rax"), self.register(*value)]);
+                    for (register, slot) in self.saved.clone() {
+                        self.emit("mov", vec![reg(register), slot]);
+                    }
+                    self.emit("mov", vec![reg("rsp"), reg("rbp")]);
+                    self.emit("pop", vec![reg("rbp")]);
+                    self.emit("ret", vec![]);
+                }
+                (Terminator::Return(None), _) => {
+                    self.emit("mov", vec![reg("rax"), imm(60)]); // sys_exit
+                    self.emit("mov", vec![reg("rdi"), imm(0)]); // return code
+                    self.emit("syscall", vec![]);
+                }
+                (Terminator::Unreachable, _) => self.emit("ud2", vec![]),
             }
-            ParsedExpr::QuotedString(_) => todo!(),
-            ParsedExpr::Var(name) => self.emit_textln(&format!("push QWORD [{}]", name)),
         }
     }
-}
 
-pub fn compile(statements: Vec<ParsedStatement>) {
-    let mut emitter = Emitter::new();
+    // Jumps to `then_id` if the condition `jump` tests for holds, else to `else_id`,
+    // falling through to whichever comes next.
+    fn emit_branch(
+        &mut self,
+        jump: &'static str,
+        then_id: BlockId,
+        else_id: BlockId,
+        next: BlockId,
+    ) {
+        if then_id == next {
+            self.emit(inverse_jump(jump), vec![self.block_label(else_id)]);
+        } else {
+            self.emit(jump, vec![self.block_label(then_id)]);
+            if else_id != next {
+                self.emit("jmp", vec![self.block_label(else_id)]);
+            }
+        }
+    }
+
+    fn emit_instruction(&mut self, function: &Function, instruction: &Instruction) {
+        match instruction {
+            Instruction::Const(dst, constant) => {
+                let dst = self.register(*dst);
+                // the register allocator made it an immediate operand
+                if dst.is_immediate() {
+                    return;
+                }
+
+                let value = imm(self.constant_operand(constant));
+                // mov to memory only takes a sign extended 32 bit immediate
+                let wide = match constant {
+                    Constant::Int(i) => i32::try_from(*i).is_err(),
+                    Constant::Float(_) => true,
+                    Constant::Bool(_) | Constant::String(_) => false,
+                };
 
-    for statement in statements {
-        match statement {
-            ParsedStatement::VarDecl(decl, expr) => match expr {
-                ParsedExpr::IntegerConstant(i) => {
-                    emitter.emit_dataln(&format!("{} dq {}", decl.name, i))
+                if wide && dst.is_memory() {
+                    self.emit("mov", vec![reg("rax"), value]);
+                    self.emit("mov", vec![dst, reg("rax")]);
+                } else {
+                    self.emit("mov", vec![dst, value]);
                 }
-                ParsedExpr::FloatConstant(_) => todo!(),
-                ParsedExpr::BinaryOp(_, _, _) => {
-                    emitter.emit_dataln(&format!("{} dq 0", decl.name));
-                    emitter.emit_expr(&expr);
-                    emitter.emit_textln("pop rdi");
-                    emitter.emit_textln(&format!("mov [{}], rdi", decl.name));
+            }
+            Instruction::Unary(dst, op, operand) => {
+                self.emit("mov", vec![reg("rax"), self.register(*operand)]);
+                match (op, function.registers[*operand]) {
+                    // flip the sign bit
+                    (UnaryOperator::Negate, Type::Float) => {
+                        self.emit("btc", vec![reg("rax"), imm(63)])
+                    }
+                    (UnaryOperator::Negate, _) => self.emit("neg", vec![reg("rax")]),
+                    (UnaryOperator::Not, Type::Bool) => self.emit("xor", vec![reg("rax"), imm(1)]),
+                    (UnaryOperator::Not, _) => self.emit("not", vec![reg("rax")]),
                 }
-                ParsedExpr::QuotedString(_) => todo!(),
-                ParsedExpr::Var(_) => todo!(),
-            },
-            ParsedStatement::FunctionCall(function, args) => {
-                if function == "print".to_string() {
-                    emitter.emit_expr(&args[0]);
-
-                    emitter.emit_textln("pop rdi");
-                    emitter.emit_textln("call print_int");
-                    emitter.emit_textln("mov rdi, 10"); // newline
-                    emitter.emit_textln("call print_char");
+                self.emit("mov", vec![self.register(*dst), reg("rax")]);
+            }
+            Instruction::Binary(dst, op, lhs, rhs) => {
+                if function.registers[*lhs] == Type::Float {
+                    self.emit_float_operator(*dst, op, *lhs, *rhs);
                 } else {
-                    todo!()
+                    self.emit_binary_operator(*dst, op, *lhs, *rhs);
                 }
             }
+            Instruction::IntToFloat(dst, src) => {
+                let mut src = self.register(*src);
+                if src.is_immediate() {
+                    self.emit("mov", vec![reg("rax"), src]);
+                    src = reg("rax");
+                }
+                self.emit("cvtsi2sd", vec![reg("xmm0"), src]);
+                self.emit("movq", vec![self.register(*dst), reg("xmm0")]);
+            }
+            Instruction::Copy(dst, src) => self.emit_move(self.register(*dst), self.register(*src)),
+            Instruction::Load(dst, id) => self.emit_move(self.register(*dst), self.global(*id)),
+            Instruction::Store(id, src) => self.emit_move(self.global(*id), self.register(*src)),
+            Instruction::Call(dst, name, args) => self.emit_call(*dst, name, args),
+            Instruction::Print(src) => self.emit_print(*src, function.registers[*src]),
         }
     }
 
-    emitter.emit_textln("mov rax, 60"); // sys_exit
-    emitter.emit_textln("mov rdi, 0"); // return code
-    emitter.emit_textln("syscall");
+    fn emit_print(&mut self, src: Reg, ty: Type) {
+        let value = self.register(src);
+
+        match ty {
+            Type::Int => {
+                self.emit("mov", vec![reg("rdi"), value]);
+                self.emit("call", vec![imm("print_int")]);
+            }
+            Type::Bool => {
+                self.emit("mov", vec![reg("rdi"), value]);
+                self.emit("call", vec![imm("print_bool")]);
+            }
+            Type::Float => {
+                self.emit("movq", vec![reg("xmm0"), value]);
+                self.emit("call", vec![imm("print_float")]);
+            }
+            Type::String => {
+                // the length is followed by the bytes
+                self.emit("mov", vec![reg("rsi"), value]);
+                self.emit("mov", vec![reg("rdx"), mem("rsi")]);
+                self.emit("add", vec![reg("rsi"), imm(8)]);
+                self.emit("call", vec![imm("print_string")]);
+            }
+        }
+        self.emit("mov", vec![reg("rdi"), imm(10)]); // newline
+        self.emit("call", vec![imm("print_char")]);
+    }
+
+    fn emit_call(&mut self, dst: Reg, function: &str, args: &[Reg]) {
+        // arguments past the registers are pushed last to first, padded to keep rsp 16
+        // byte aligned at the call
+        let stack_args = args.len().saturating_sub(ARG_REGISTERS.len());
+        let padding = stack_args % 2;
+        if padding > 0 {
+            self.emit("sub", vec![reg("rsp"), imm(8)]);
+        }
+        for arg in args.iter().skip(ARG_REGISTERS.len()).rev() {
+            self.emit("push", vec![self.register(*arg)]);
+        }
+
+        for (arg, register) in args.iter().zip(ARG_REGISTERS) {
+            self.emit("mov", vec![reg(register), self.register(*arg)]);
+        }
+
+        self.emit("call", vec![imm(format!("fn_{}", function))]);
+        if stack_args > 0 {
+            self.emit("add", vec![reg("rsp"), imm(8 * (stack_args + padding))]);
+        }
+        self.emit("mov", vec![self.register(dst), reg("rax")]);
+    }
 
-    write_asm_file(emitter);
-    compile_asm_file();
-    link_source();
-    run_source();
-    clean_up();
+    fn emit_comparison(&mut self, set_instruction: &'static str, rhs: Operand) {
+        self.emit("cmp", vec![reg("rax"), rhs]);
+        self.emit(set_instruction, vec![reg("al")]);
+        self.emit("movzx", vec![reg("rax"), reg("al")]);
+    }
+
+    fn emit_float_comparison(&mut self, set_instruction: &'static str, swap: bool) {
+        // ucomisd sets the flags like an unsigned compare, and unordered (NaN)
+        // operands set ZF, PF and CF, so use the above conditions where possible
+        if swap {
+            self.emit("ucomisd", vec![reg("xmm1"), reg("xmm0")]);
+        } else {
+            self.emit("ucomisd", vec![reg("xmm0"), reg("xmm1")]);
+        }
+        self.emit(set_instruction, vec![reg("al")]);
+        match set_instruction {
+            "sete" => {
+                self.emit("setnp", vec![reg("cl")]);
+                self.emit("and", vec![reg("al"), reg("cl")]);
+            }
+            "setne" => {
+                self.emit("setp", vec![reg("cl")]);
+                self.emit("or", vec![reg("al"), reg("cl")]);
+            }
+            _ => {}
+        }
+        self.emit("movzx", vec![reg("rax"), reg("al")]);
+    }
+
+    fn emit_float_arithmetic(&mut self, instruction: &'static str) {
+        self.emit(instruction, vec![reg("xmm0"), reg("xmm1")]);
+        self.emit("movq", vec![reg("rax"), reg("xmm0")]);
+    }
+
+    // Both operands have already been converted to floats by the typechecker.
+    fn emit_float_operator(&mut self, dst: Reg, op: &BinaryOperator, lhs: Reg, rhs: Reg) {
+        self.emit("movq", vec![reg("xmm0"), self.register(lhs)]);
+        self.emit("movq", vec![reg("xmm1"), self.register(rhs)]);
+
+        match op {
+            BinaryOperator::Add => self.emit_float_arithmetic("addsd"),
+            BinaryOperator::Subtract => self.emit_float_arithmetic("subsd"),
+            BinaryOperator::Multiply => self.emit_float_arithmetic("mulsd"),
+            BinaryOperator::Divide => self.emit_float_arithmetic("divsd"),
+            BinaryOperator::Equal => self.emit_float_comparison("sete", false),
+            BinaryOperator::NotEqual => self.emit_float_comparison("setne", false),
+            BinaryOperator::LessThan => self.emit_float_comparison("seta", true),
+            BinaryOperator::LessThanOrEqual => self.emit_float_comparison("setae", true),
+            BinaryOperator::GreaterThan => self.emit_float_comparison("seta", false),
+            BinaryOperator::GreaterThanOrEqual => self.emit_float_comparison("setae", false),
+            _ => unreachable!("operator '{}' on floats", op),
+        }
+        self.emit("mov", vec![self.register(dst), reg("rax")]);
+    }
+
+    fn emit_binary_operator(&mut self, dst: Reg, op: &BinaryOperator, lhs: Reg, rhs: Reg) {
+        let rhs = self.register(rhs);
+        let dst = self.register(dst);
+
+        // two operand instructions can work on the destination directly, as long as
+        // it isn't in memory and moving lhs into it doesn't clobber rhs
+        let direct = match op {
+            BinaryOperator::Add => Some("add"),
+            BinaryOperator::Subtract => Some("sub"),
+            BinaryOperator::Multiply => Some("imul"),
+            BinaryOperator::BitwiseAnd => Some("and"),
+            BinaryOperator::BitwiseOr => Some("or"),
+            BinaryOperator::BitwiseXor => Some("xor"),
+            _ => None,
+        };
+        if let Some(instruction) = direct {
+            if !dst.is_memory() && dst != rhs {
+                self.emit_move(dst.clone(), self.register(lhs));
+                self.emit(instruction, vec![dst, rhs]);
+                return;
+            }
+        }
+
+        self.emit("mov", vec![reg("rax"), self.register(lhs)]);
+
+        match op {
+            BinaryOperator::Add => self.emit("add", vec![reg("rax"), rhs]),
+            BinaryOperator::Subtract => self.emit("sub", vec![reg("rax"), rhs]),
+            BinaryOperator::Multiply => self.emit("imul", vec![reg("rax"), rhs]),
+            BinaryOperator::Divide | BinaryOperator::Modulo => {
+                self.emit("cqo", vec![]); // sign extend rax into rdx
+                if rhs.is_immediate() {
+                    self.emit("mov", vec![reg("rcx"), rhs]);
+                    self.emit("idiv", vec![reg("rcx")]);
+                } else {
+                    self.emit("idiv", vec![rhs]);
+                }
+                if *op == BinaryOperator::Modulo {
+                    self.emit("mov", vec![reg("rax"), reg("rdx")]);
+                }
+            }
+            BinaryOperator::BitwiseAnd => self.emit("and", vec![reg("rax"), rhs]),
+            BinaryOperator::BitwiseOr => self.emit("or", vec![reg("rax"), rhs]),
+            BinaryOperator::BitwiseXor => self.emit("xor", vec![reg("rax"), rhs]),
+            BinaryOperator::ShiftLeft | BinaryOperator::ShiftRight => {
+                let instruction = match op {
+                    BinaryOperator::ShiftLeft => "sal",
+                    _ => "sar",
+                };
+                self.emit("mov", vec![reg("rcx"), rhs]);
+                self.emit(instruction, vec![reg("rax"), reg("cl")]);
+            }
+            BinaryOperator::LogicalAnd | BinaryOperator::LogicalOr => {
+                unreachable!("logical operators are lowered to branches")
+            }
+            BinaryOperator::Equal => self.emit_comparison("sete", rhs),
+            BinaryOperator::NotEqual => self.emit_comparison("setne", rhs),
+            BinaryOperator::LessThan => self.emit_comparison("setl", rhs),
+            BinaryOperator::LessThanOrEqual => self.emit_comparison("setle", rhs),
+            BinaryOperator::GreaterThan => self.emit_comparison("setg", rhs),
+            BinaryOperator::GreaterThanOrEqual => self.emit_comparison("setge", rhs),
+        }
+        self.emit("mov", vec![dst, reg("rax")]);
+    }
+}
+
+// Returns the program as nasm assembly, with the peephole optimizations applied if
+// `optimize` is set.
+pub fn compile(program: &Program, optimize: bool) -> String {
+    let mut emitter = Emitter::new(&program.variables);
+
+    emitter.emit_globals(program);
+    emitter.begin_function(&program.main);
+    emitter.emit_blocks(&program.main);
+
+    for function in &program.functions {
+        emitter.emit_function(function);
+    }
+
+    if optimize {
+        peephole::optimize(&mut emitter.output_text);
+    }
+
+    let mut asm = emitter.output_data;
+    asm.push_str("SECTION .text\n");
+    for line in &emitter.output_text {
+        asm.push_str(&format!("{}\n", line));
+    }
+    asm
 }
 
-fn write_asm_file(emitter: Emitter) {
-    let mut file = File::create("a.asm").expect("Failed to create output file.");
-    file.write_all(
-        &[
-            emitter.output_data.as_bytes(),
-            emitter.output_text.as_bytes(),
-        ]
-        .concat(),
-    )
-    .expect("Failed to write assembly to output file.");
+// An integer comparison computing a block's branch condition, and nothing else, jumps
+// on the flags directly instead of materializing a bool first.
+fn fused_comparison(function: &Function, id: BlockId) -> Option<(&BinaryOperator, Reg, Reg)> {
+    let block = &function.blocks[id];
+    let Terminator::Branch(condition, _, _) = block.terminator else {
+        return None;
+    };
+
+    match block.instructions.last() {
+        Some(Instruction::Binary(dst, op, lhs, rhs))
+            if *dst == condition
+                && op.is_comparison()
+                && function.registers[*lhs] != Type::Float
+                && uses(function, condition) == 1 =>
+        {
+            Some((op, *lhs, *rhs))
+        }
+        _ => None,
+    }
 }
 
-fn compile_asm_file() {
-    let compile_output = Command::new("nasm")
-        .arg("-f")
-        .arg("elf64")
-        .arg("a.asm")
-        .arg("-o")
-        .arg("a.o")
-        .output()
-        .expect("Error assembling code.");
-    print!(
-        "{}{}",
-        String::from_utf8_lossy(&compile_output.stdout),
-        String::from_utf8_lossy(&compile_output.stderr)
-    );
-
-    let compile_lib = Command::new("nasm")
-        .arg("-f")
-        .arg("elf64")
-        .arg("lib.asm")
-        .arg("-o")
-        .arg("lib.o")
-        .output()
-        .expect("Error assembling code.");
-    print!(
-        "{}{}",
-        String::from_utf8_lossy(&compile_lib.stdout),
-        String::from_utf8_lossy(&compile_lib.stderr)
-    );
+fn uses(function: &Function, reg: Reg) -> usize {
+    function
+        .blocks
+        .iter()
+        .flat_map(|block| {
+            block
+                .instructions
+                .iter()
+                .flat_map(Instruction::operands)
+                .chain(block.terminator.operands())
+        })
+        .filter(|operand| *operand == reg)
+        .count()
 }
 
-fn link_source() {
-    let link_output = Command::new("ld")
-        .arg("-m")
-        .arg("elf_x86_64")
-        .arg("a.o")
-        .arg("lib.o")
-        .arg("-o")
-        .arg("a.out")
-        .output()
-        .expect("Error linking code.");
-
-    print!(
-        "{}{}",
-        String::from_utf8_lossy(&link_output.stdout),
-        String::from_utf8_lossy(&link_output.stderr)
-    );
+fn inverse_jump(jump: &str) -> &'static str {
+    match jump {
+        "je" => "jne",
+        "jne" => "je",
+        "jl" => "jge",
+        "jle" => "jg",
+        "jg" => "jle",
+        _ => "jl",
+    }
 }
 
-fn run_source() {
-    let run_output = Command::new("./a.out")
-        .output()
-        .expect("Error running code.");
-    print!(
-        "{}{}",
-        String::from_utf8_lossy(&run_output.stdout),
-        String::from_utf8_lossy(&run_output.stderr)
-    );
+// Floats are written as their bit pattern, so no precision is lost.
+fn float_bits(f: f64) -> String {
+    format!("0x{:016x}", f.to_bits())
 }
 
-fn clean_up() {
-    Command::new("rm")
-        // .arg("a.asm")
-        .arg("a.o")
-        // .arg("a.out")
-        .spawn()
-        .expect("Failed to run cleanup.");
+// Formats a string as `db` operands, keeping printable runs inside quotes.
+fn string_bytes(string: &str) -> String {
+    let mut operands = vec![];
+    let mut run = String::new();
+
+    for byte in string.bytes() {
+        if (b' '..=b'~').contains(&byte) && byte != b'"' {
+            run.push(byte as char);
+        } else {
+            if !run.is_empty() {
+                operands.push(format!("\"{}\"", run));
+                run.clear();
+            }
+            operands.push(byte.to_string());
+        }
+    }
+
+    if !run.is_empty() {
+        operands.push(format!("\"{}\"", run));
+    }
+
+    operands.join(", ")
 }
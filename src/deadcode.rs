@@ -0,0 +1,95 @@
+use std::mem;
+
+use crate::{
+    ir::{Function, Instruction, Program, Terminator},
+    liveness,
+};
+
+// Removes the blocks that can never run, like code after a `return` or `break`, and
+// the instructions computing values that are never read.
+pub fn eliminate(program: &mut Program) {
+    for function in std::iter::once(&mut program.main).chain(&mut program.functions) {
+        remove_unreachable_blocks(function);
+        while remove_dead_instructions(function) {}
+    }
+}
+
+fn remove_unreachable_blocks(function: &mut Function) {
+    let mut reachable = vec![false; function.blocks.len()];
+    let mut pending = vec![0];
+    while let Some(id) = pending.pop() {
+        if !reachable[id] {
+            reachable[id] = true;
+            pending.extend(function.blocks[id].terminator.successors());
+        }
+    }
+
+    // the blocks that are left keep their order, so the layout doesn't change
+    let mut new_ids = vec![0; reachable.len()];
+    let mut next = 0;
+    for (id, reachable) in reachable.iter().enumerate() {
+        new_ids[id] = next;
+        if *reachable {
+            next += 1;
+        }
+    }
+
+    let blocks = mem::take(&mut function.blocks);
+    for (mut block, reachable) in blocks.into_iter().zip(reachable) {
+        if !reachable {
+            continue;
+        }
+
+        match &mut block.terminator {
+            Terminator::Jump(target) => *target = new_ids[*target],
+            Terminator::Branch(_, then_id, else_id) => {
+                *then_id = new_ids[*then_id];
+                *else_id = new_ids[*else_id];
+            }
+            Terminator::Return(_) | Terminator::Unreachable => {}
+        }
+        function.blocks.push(block);
+    }
+}
+
+// Returns whether anything was removed. Removing an instruction can make the ones
+// computing its operands dead in other blocks, so this is repeated until it isn't.
+fn remove_dead_instructions(function: &mut Function) -> bool {
+    let liveness = liveness::analyze(function);
+    let mut changed = false;
+
+    for (id, block) in function.blocks.iter_mut().enumerate() {
+        let mut live = liveness.live_out[id].clone();
+        live.extend(block.terminator.operands());
+
+        let mut kept = vec![];
+        for instruction in block.instructions.drain(..).rev() {
+            let needed = has_side_effects(&instruction)
+                || instruction
+                    .destination()
+                    .is_some_and(|dst| live.contains(&dst));
+            if !needed {
+                changed = true;
+                continue;
+            }
+
+            if let Some(dst) = instruction.destination() {
+                live.remove(&dst);
+            }
+            live.extend(instruction.operands());
+            kept.push(instruction);
+        }
+
+        kept.reverse();
+        block.instructions = kept;
+    }
+
+    changed
+}
+
+fn has_side_effects(instruction: &Instruction) -> bool {
+    matches!(
+        instruction,
+        Instruction::Call(..) | Instruction::Print(_) | Instruction::Store(..)
+    )
+}
@@ -0,0 +1,201 @@
+use crate::{
+    lexer::Span,
+    parser::{BinaryOperator, ParsedBlock, ParsedExpr, ParsedStatement, UnaryOperator},
+    JPLError,
+};
+
+// Evaluates operators on constants at compile time, so they become single immediates
+// and constant globals can be initialized in .data. Operands whose types don't fit
+// the operator are left alone for the typechecker to report.
+pub fn fold(statements: &mut [ParsedStatement]) -> Result<(), Vec<JPLError>> {
+    let mut errors = vec![];
+    fold_statements(statements, &mut errors);
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+fn fold_statements(statements: &mut [ParsedStatement], errors: &mut Vec<JPLError>) {
+    for statement in statements {
+        match statement {
+            ParsedStatement::VarDecl(_, expr)
+            | ParsedStatement::Assignment(_, _, expr, _)
+            | ParsedStatement::Return(Some(expr), _)
+            | ParsedStatement::Expression(expr) => fold_expr(expr, errors),
+            ParsedStatement::FunctionDecl(function) => fold_block(&mut function.body, errors),
+            ParsedStatement::If(condition, then_block, else_block) => {
+                fold_expr(condition, errors);
+                fold_block(then_block, errors);
+                if let Some(else_block) = else_block {
+                    fold_block(else_block, errors);
+                }
+            }
+            ParsedStatement::While(condition, body) => {
+                fold_expr(condition, errors);
+                fold_block(body, errors);
+            }
+            ParsedStatement::Block(block) => fold_block(block, errors),
+            ParsedStatement::Return(None, _)
+            | ParsedStatement::Break
+            | ParsedStatement::Continue => {}
+        }
+    }
+}
+
+fn fold_block(block: &mut ParsedBlock, errors: &mut Vec<JPLError>) {
+    fold_statements(&mut block.statements, errors);
+}
+
+fn fold_expr(expr: &mut ParsedExpr, errors: &mut Vec<JPLError>) {
+    let folded = match expr {
+        ParsedExpr::UnaryOp(op, operand, span) => {
+            fold_expr(operand, errors);
+            fold_unary_op(op, operand, *span)
+        }
+        ParsedExpr::BinaryOp(lhs, op, rhs, span) => {
+            fold_expr(lhs, errors);
+            fold_expr(rhs, errors);
+            fold_binary_op(lhs, op, rhs, *span)
+        }
+        ParsedExpr::Call(_, args, _) => {
+            for arg in args {
+                fold_expr(arg, errors);
+            }
+            Ok(None)
+        }
+        _ => Ok(None),
+    };
+
+    match folded {
+        Ok(Some(constant)) => *expr = constant,
+        Ok(None) => {}
+        Err(error) => errors.push(error),
+    }
+}
+
+fn fold_unary_op(
+    op: &UnaryOperator,
+    operand: &ParsedExpr,
+    span: Span,
+) -> Result<Option<ParsedExpr>, JPLError> {
+    let folded = match (op, operand) {
+        (UnaryOperator::Negate, ParsedExpr::IntegerConstant(i, _)) => {
+            ParsedExpr::IntegerConstant(i.checked_neg().ok_or_else(|| overflow(span))?, span)
+        }
+        (UnaryOperator::Negate, ParsedExpr::FloatConstant(f, _)) => {
+            ParsedExpr::FloatConstant(-f, span)
+        }
+        (UnaryOperator::Not, ParsedExpr::IntegerConstant(i, _)) => {
+            ParsedExpr::IntegerConstant(!i, span)
+        }
+        (UnaryOperator::Not, ParsedExpr::BoolConstant(b, _)) => ParsedExpr::BoolConstant(!b, span),
+        _ => return Ok(None),
+    };
+
+    Ok(Some(folded))
+}
+
+fn fold_binary_op(
+    lhs: &ParsedExpr,
+    op: &BinaryOperator,
+    rhs: &ParsedExpr,
+    span: Span,
+) -> Result<Option<ParsedExpr>, JPLError> {
+    match (lhs, rhs) {
+        (ParsedExpr::IntegerConstant(a, _), ParsedExpr::IntegerConstant(b, _)) => {
+            fold_int_op(*a, op, *b, span)
+        }
+        // mixed arithmetic is done in floating point
+        (ParsedExpr::FloatConstant(a, _), ParsedExpr::FloatConstant(b, _)) => {
+            Ok(fold_float_op(*a, op, *b, span))
+        }
+        (ParsedExpr::IntegerConstant(a, _), ParsedExpr::FloatConstant(b, _)) => {
+            Ok(fold_float_op(*a as f64, op, *b, span))
+        }
+        (ParsedExpr::FloatConstant(a, _), ParsedExpr::IntegerConstant(b, _)) => {
+            Ok(fold_float_op(*a, op, *b as f64, span))
+        }
+        (ParsedExpr::BoolConstant(a, _), ParsedExpr::BoolConstant(b, _)) => {
+            Ok(fold_bool_op(*a, op, *b, span))
+        }
+        _ => Ok(None),
+    }
+}
+
+fn fold_int_op(
+    a: i64,
+    op: &BinaryOperator,
+    b: i64,
+    span: Span,
+) -> Result<Option<ParsedExpr>, JPLError> {
+    if matches!(op, BinaryOperator::Divide | BinaryOperator::Modulo) && b == 0 {
+        return Err(JPLError::new(
+            "Division by zero in constant expression.".to_string(),
+            span,
+        ));
+    }
+
+    let value = match op {
+        BinaryOperator::Add => a.checked_add(b),
+        BinaryOperator::Subtract => a.checked_sub(b),
+        BinaryOperator::Multiply => a.checked_mul(b),
+        BinaryOperator::Divide => a.checked_div(b),
+        BinaryOperator::Modulo => a.checked_rem(b),
+        BinaryOperator::BitwiseAnd => Some(a & b),
+        BinaryOperator::BitwiseOr => Some(a | b),
+        BinaryOperator::BitwiseXor => Some(a ^ b),
+        // like sal and sar, the shift amount is taken modulo 64
+        BinaryOperator::ShiftLeft => Some(a.wrapping_shl(b as u32)),
+        BinaryOperator::ShiftRight => Some(a.wrapping_shr(b as u32)),
+        BinaryOperator::LogicalAnd | BinaryOperator::LogicalOr => return Ok(None),
+        _ => return Ok(Some(ParsedExpr::BoolConstant(compare(a, op, b), span))),
+    };
+
+    match value {
+        Some(value) => Ok(Some(ParsedExpr::IntegerConstant(value, span))),
+        None => Err(overflow(span)),
+    }
+}
+
+fn fold_float_op(a: f64, op: &BinaryOperator, b: f64, span: Span) -> Option<ParsedExpr> {
+    let value = match op {
+        BinaryOperator::Add => a + b,
+        BinaryOperator::Subtract => a - b,
+        BinaryOperator::Multiply => a * b,
+        BinaryOperator::Divide => a / b,
+        _ if op.is_comparison() => return Some(ParsedExpr::BoolConstant(compare(a, op, b), span)),
+        _ => return None,
+    };
+
+    Some(ParsedExpr::FloatConstant(value, span))
+}
+
+fn fold_bool_op(a: bool, op: &BinaryOperator, b: bool, span: Span) -> Option<ParsedExpr> {
+    let value = match op {
+        BinaryOperator::LogicalAnd | BinaryOperator::BitwiseAnd => a & b,
+        BinaryOperator::LogicalOr | BinaryOperator::BitwiseOr => a | b,
+        BinaryOperator::BitwiseXor | BinaryOperator::NotEqual => a ^ b,
+        BinaryOperator::Equal => a == b,
+        _ => return None,
+    };
+
+    Some(ParsedExpr::BoolConstant(value, span))
+}
+
+fn compare<T: PartialOrd>(a: T, op: &BinaryOperator, b: T) -> bool {
+    match op {
+        BinaryOperator::Equal => a == b,
+        BinaryOperator::NotEqual => a != b,
+        BinaryOperator::LessThan => a < b,
+        BinaryOperator::LessThanOrEqual => a <= b,
+        BinaryOperator::GreaterThan => a > b,
+        _ => a >= b,
+    }
+}
+
+fn overflow(span: Span) -> JPLError {
+    JPLError::new("Integer overflow in constant expression.".to_string(), span)
+}
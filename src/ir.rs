@@ -0,0 +1,661 @@
+use std::{collections::HashMap, fmt, mem};
+
+use crate::{
+    parser::{BinaryOperator, UnaryOperator},
+    symbols::VarId,
+    typechecker::{
+        CheckedBlock, CheckedExpr, CheckedExprKind, CheckedProgram, CheckedStatement,
+        CheckedVariable, Type,
+    },
+};
+
+// A virtual register, holding a temporary or a local variable. Unlike SSA values
+// they may be assigned more than once, which is also how the result of a
+// short-circuiting `&&` or `||` is merged.
+pub type Reg = usize;
+pub type BlockId = usize;
+
+#[derive(Debug)]
+pub struct Program {
+    // the top-level statements, which return to the exit syscall
+    pub main: Function,
+    pub functions: Vec<Function>,
+    pub variables: Vec<CheckedVariable>,
+    // globals initialized with a constant, which need no code; other globals start at 0
+    pub initializers: HashMap<VarId, Constant>,
+}
+
+#[derive(Debug)]
+pub struct Function {
+    pub name: String,
+    pub params: Vec<Reg>,
+    // None for the main program
+    pub return_type: Option<Type>,
+    // blocks[0] is the entry
+    pub blocks: Vec<Block>,
+    // the type of each register
+    pub registers: Vec<Type>,
+}
+
+#[derive(Debug)]
+pub struct Block {
+    pub instructions: Vec<Instruction>,
+    pub terminator: Terminator,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Constant {
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    String(String),
+}
+
+// The destination register comes first.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Instruction {
+    Const(Reg, Constant),
+    Copy(Reg, Reg),
+    Unary(Reg, UnaryOperator, Reg),
+    // never a logical operator, those are lowered to branches
+    Binary(Reg, BinaryOperator, Reg, Reg),
+    IntToFloat(Reg, Reg),
+    // globals live in memory, as every function can use them
+    Load(Reg, VarId),
+    Store(VarId, Reg),
+    Call(Reg, String, Vec<Reg>),
+    Print(Reg),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Terminator {
+    Jump(BlockId),
+    // to the first block if the bool register is true, else to the second
+    Branch(Reg, BlockId, BlockId),
+    // None only for the main program
+    Return(Option<Reg>),
+    // the end of a function that returns on every path
+    Unreachable,
+}
+
+impl Instruction {
+    // The register written, if any.
+    pub fn destination(&self) -> Option<Reg> {
+        match self {
+            Instruction::Const(dst, _)
+            | Instruction::Copy(dst, _)
+            | Instruction::Unary(dst, _, _)
+            | Instruction::Binary(dst, _, _, _)
+            | Instruction::IntToFloat(dst, _)
+            | Instruction::Load(dst, _)
+            | Instruction::Call(dst, _, _) => Some(*dst),
+            Instruction::Store(_, _) | Instruction::Print(_) => None,
+        }
+    }
+
+    // The registers read.
+    pub fn operands(&self) -> Vec<Reg> {
+        match self {
+            Instruction::Const(_, _) | Instruction::Load(_, _) => vec![],
+            Instruction::Copy(_, src)
+            | Instruction::Unary(_, _, src)
+            | Instruction::IntToFloat(_, src)
+            | Instruction::Store(_, src)
+            | Instruction::Print(src) => vec![*src],
+            Instruction::Binary(_, _, lhs, rhs) => vec![*lhs, *rhs],
+            Instruction::Call(_, _, args) => args.clone(),
+        }
+    }
+}
+
+impl Terminator {
+    pub fn successors(&self) -> Vec<BlockId> {
+        match self {
+            Terminator::Jump(target) => vec![*target],
+            Terminator::Branch(_, then_id, else_id) => vec![*then_id, *else_id],
+            Terminator::Return(_) | Terminator::Unreachable => vec![],
+        }
+    }
+
+    pub fn operands(&self) -> Vec<Reg> {
+        match self {
+            Terminator::Jump(_) | Terminator::Return(None) | Terminator::Unreachable => vec![],
+            Terminator::Branch(condition, _, _) => vec![*condition],
+            Terminator::Return(Some(value)) => vec![*value],
+        }
+    }
+}
+
+pub fn lower(program: CheckedProgram) -> Program {
+    let mut initializers = HashMap::new();
+
+    let mut lowerer = Lowerer::new(&program.variables, Some(&mut initializers), None, true);
+    lowerer.lower_statements(&program.statements);
+    let main = lowerer.finish("main".to_string(), vec![]);
+
+    let functions = program
+        .functions
+        .iter()
+        .map(|function| {
+            let mut lowerer = Lowerer::new(
+                &program.variables,
+                None,
+                Some(function.return_type),
+                function.implicit_return,
+            );
+            let params = function
+                .params
+                .iter()
+                .map(|param| lowerer.local(*param))
+                .collect();
+            lowerer.lower_block(&function.body);
+            lowerer.finish(function.name.clone(), params)
+        })
+        .collect();
+
+    Program {
+        main,
+        functions,
+        variables: program.variables,
+        initializers,
+    }
+}
+
+struct Lowerer<'a> {
+    variables: &'a [CheckedVariable],
+    // only set for the main program
+    initializers: Option<&'a mut HashMap<VarId, Constant>>,
+    // None for the main program
+    return_type: Option<Type>,
+    implicit_return: bool,
+    instructions: Vec<Vec<Instruction>>,
+    terminators: Vec<Option<Terminator>>,
+    registers: Vec<Type>,
+    // the register of each local variable
+    locals: HashMap<VarId, Reg>,
+    // None after a jump or return, until the next block starts
+    current: Option<BlockId>,
+    // the blocks in the order they were started, which is the order they're laid out in
+    order: Vec<BlockId>,
+    // (continue, break) targets of the enclosing loops
+    loops: Vec<(BlockId, BlockId)>,
+}
+
+impl<'a> Lowerer<'a> {
+    fn new(
+        variables: &'a [CheckedVariable],
+        initializers: Option<&'a mut HashMap<VarId, Constant>>,
+        return_type: Option<Type>,
+        implicit_return: bool,
+    ) -> Self {
+        let mut lowerer = Self {
+            variables,
+            initializers,
+            return_type,
+            implicit_return,
+            instructions: vec![],
+            terminators: vec![],
+            registers: vec![],
+            locals: HashMap::new(),
+            current: None,
+            order: vec![],
+            loops: vec![],
+        };
+
+        let entry = lowerer.new_block();
+        lowerer.start_block(entry);
+        lowerer
+    }
+
+    fn finish(mut self, name: String, params: Vec<Reg>) -> Function {
+        // falling off the end of a function without a declared return type returns 0,
+        // the typechecker makes sure the others never get there
+        if self.current.is_some() {
+            let terminator = match self.return_type {
+                Some(ty) if self.implicit_return => Terminator::Return(Some(self.zero_value(ty))),
+                Some(_) => Terminator::Unreachable,
+                None => Terminator::Return(None),
+            };
+            self.terminate(terminator);
+        }
+
+        // blocks are created before the code jumping to them, renumber them so code
+        // follows on from the code before it where possible
+        let mut position = vec![0; self.order.len()];
+        for (i, block) in self.order.iter().enumerate() {
+            position[*block] = i;
+        }
+
+        let blocks = self
+            .order
+            .iter()
+            .map(|block| {
+                let terminator = match self.terminators[*block].take() {
+                    Some(Terminator::Jump(target)) => Terminator::Jump(position[target]),
+                    Some(Terminator::Branch(condition, then_id, else_id)) => {
+                        Terminator::Branch(condition, position[then_id], position[else_id])
+                    }
+                    Some(Terminator::Return(value)) => Terminator::Return(value),
+                    Some(Terminator::Unreachable) => Terminator::Unreachable,
+                    None => unreachable!("unterminated block"),
+                };
+
+                Block {
+                    instructions: mem::take(&mut self.instructions[*block]),
+                    terminator,
+                }
+            })
+            .collect();
+
+        Function {
+            name,
+            params,
+            return_type: self.return_type,
+            blocks,
+            registers: self.registers,
+        }
+    }
+
+    fn new_block(&mut self) -> BlockId {
+        self.instructions.push(vec![]);
+        self.terminators.push(None);
+        self.instructions.len() - 1
+    }
+
+    fn new_register(&mut self, ty: Type) -> Reg {
+        self.registers.push(ty);
+        self.registers.len() - 1
+    }
+
+    fn local(&mut self, id: VarId) -> Reg {
+        if let Some(reg) = self.locals.get(&id) {
+            return *reg;
+        }
+
+        let reg = self.new_register(self.variables[id].ty);
+        self.locals.insert(id, reg);
+        reg
+    }
+
+    // Code after a return, break or continue goes in a block nothing jumps to.
+    fn current_block(&mut self) -> BlockId {
+        match self.current {
+            Some(block) => block,
+            None => {
+                let block = self.new_block();
+                self.start_block(block);
+                block
+            }
+        }
+    }
+
+    fn emit(&mut self, instruction: Instruction) {
+        let block = self.current_block();
+        self.instructions[block].push(instruction);
+    }
+
+    fn terminate(&mut self, terminator: Terminator) {
+        let block = self.current_block();
+        self.terminators[block] = Some(terminator);
+        self.current = None;
+    }
+
+    fn start_block(&mut self, block: BlockId) {
+        self.current = Some(block);
+        self.order.push(block);
+    }
+
+    fn zero_value(&mut self, ty: Type) -> Reg {
+        let constant = match ty {
+            Type::Int => Constant::Int(0),
+            Type::Float => Constant::Float(0.0),
+            Type::Bool => Constant::Bool(false),
+            Type::String => Constant::String(String::new()),
+        };
+
+        let reg = self.new_register(ty);
+        self.emit(Instruction::Const(reg, constant));
+        reg
+    }
+
+    fn lower_statements(&mut self, statements: &[CheckedStatement]) {
+        for statement in statements {
+            self.lower_statement(statement);
+        }
+    }
+
+    fn lower_block(&mut self, block: &CheckedBlock) {
+        self.lower_statements(&block.statements);
+    }
+
+    fn lower_statement(&mut self, statement: &CheckedStatement) {
+        match statement {
+            CheckedStatement::VarDecl(id, expr) => {
+                // nothing can use a global before its declaration, as the typechecker
+                // rejects calls to functions using it until then, so a constant value
+                // may as well be there from the start
+                if self.variables[*id].global && self.variables[*id].used {
+                    if let (Some(initializers), Some(constant)) =
+                        (self.initializers.as_deref_mut(), constant(expr))
+                    {
+                        initializers.insert(*id, constant);
+                        return;
+                    }
+                }
+
+                self.lower_assignment(*id, expr);
+            }
+            CheckedStatement::Assignment(id, expr) => self.lower_assignment(*id, expr),
+            CheckedStatement::Return(expr) => {
+                let value = match expr {
+                    Some(expr) => self.lower_expr(expr),
+                    None => {
+                        let ty = self.return_type.expect("return outside of function");
+                        self.zero_value(ty)
+                    }
+                };
+                self.terminate(Terminator::Return(Some(value)));
+            }
+            CheckedStatement::If(condition, then_block, else_block) => {
+                let then_id = self.new_block();
+                let end_id = self.new_block();
+                let else_id = match else_block {
+                    Some(_) => self.new_block(),
+                    None => end_id,
+                };
+
+                self.lower_condition(condition, then_id, else_id);
+
+                self.start_block(then_id);
+                self.lower_block(then_block);
+                self.terminate(Terminator::Jump(end_id));
+
+                if let Some(else_block) = else_block {
+                    self.start_block(else_id);
+                    self.lower_block(else_block);
+                    self.terminate(Terminator::Jump(end_id));
+                }
+
+                self.start_block(end_id);
+            }
+            CheckedStatement::While(condition, body) => {
+                let head_id = self.new_block();
+                let body_id = self.new_block();
+                let exit_id = self.new_block();
+
+                self.terminate(Terminator::Jump(head_id));
+                self.start_block(head_id);
+                self.lower_condition(condition, body_id, exit_id);
+
+                self.start_block(body_id);
+                self.loops.push((head_id, exit_id));
+                self.lower_block(body);
+                self.loops.pop();
+                self.terminate(Terminator::Jump(head_id));
+
+                self.start_block(exit_id);
+            }
+            CheckedStatement::Break => {
+                let (_, exit_id) = *self.loops.last().expect("break outside of loop");
+                self.terminate(Terminator::Jump(exit_id));
+            }
+            CheckedStatement::Continue => {
+                let (head_id, _) = *self.loops.last().expect("continue outside of loop");
+                self.terminate(Terminator::Jump(head_id));
+            }
+            CheckedStatement::Block(block) => self.lower_block(block),
+            CheckedStatement::Expression(expr) => {
+                self.lower_expr(expr);
+            }
+        }
+    }
+
+    // Jumps to `then_id` if the bool condition is true, else to `else_id`. The logical
+    // operators become jumps rather than values here.
+    fn lower_condition(&mut self, condition: &CheckedExpr, then_id: BlockId, else_id: BlockId) {
+        match &condition.kind {
+            CheckedExprKind::BinaryOp(lhs, BinaryOperator::LogicalAnd, rhs) => {
+                let rhs_id = self.new_block();
+                self.lower_condition(lhs, rhs_id, else_id);
+                self.start_block(rhs_id);
+                self.lower_condition(rhs, then_id, else_id);
+            }
+            CheckedExprKind::BinaryOp(lhs, BinaryOperator::LogicalOr, rhs) => {
+                let rhs_id = self.new_block();
+                self.lower_condition(lhs, then_id, rhs_id);
+                self.start_block(rhs_id);
+                self.lower_condition(rhs, then_id, else_id);
+            }
+            CheckedExprKind::UnaryOp(UnaryOperator::Not, operand) => {
+                self.lower_condition(operand, else_id, then_id);
+            }
+            _ => {
+                let value = self.lower_expr(condition);
+                self.terminate(Terminator::Branch(value, then_id, else_id));
+            }
+        }
+    }
+
+    fn lower_assignment(&mut self, id: VarId, expr: &CheckedExpr) {
+        // only the side effects of a value that is never read are kept
+        if !self.variables[id].used {
+            self.lower_expr(expr);
+            return;
+        }
+
+        if self.variables[id].global {
+            let value = self.lower_expr(expr);
+            self.emit(Instruction::Store(id, value));
+        } else {
+            let reg = self.local(id);
+            self.lower_expr_into(expr, reg);
+        }
+    }
+
+    fn lower_expr(&mut self, expr: &CheckedExpr) -> Reg {
+        // nothing in an expression can assign to a local, so it can be read in place
+        if let CheckedExprKind::Var(id) = &expr.kind {
+            if !self.variables[*id].global {
+                return self.local(*id);
+            }
+        }
+
+        let dst = self.new_register(expr.ty);
+        self.lower_expr_into(expr, dst);
+        dst
+    }
+
+    // Every instruction reads its operands before writing its destination, so `dst`
+    // may be one of the expression's operands.
+    fn lower_expr_into(&mut self, expr: &CheckedExpr, dst: Reg) {
+        let instruction = match &expr.kind {
+            CheckedExprKind::IntegerConstant(i) => Instruction::Const(dst, Constant::Int(*i)),
+            CheckedExprKind::FloatConstant(f) => Instruction::Const(dst, Constant::Float(*f)),
+            CheckedExprKind::BoolConstant(b) => Instruction::Const(dst, Constant::Bool(*b)),
+            CheckedExprKind::QuotedString(s) => {
+                Instruction::Const(dst, Constant::String(s.clone()))
+            }
+            CheckedExprKind::BinaryOp(_, op, _) if op.is_logical() => {
+                return self.lower_logical(expr, dst);
+            }
+            CheckedExprKind::UnaryOp(op, operand) => {
+                let operand = self.lower_expr(operand);
+                Instruction::Unary(dst, op.clone(), operand)
+            }
+            CheckedExprKind::BinaryOp(lhs, op, rhs) => {
+                let lhs = self.lower_expr(lhs);
+                let rhs = self.lower_expr(rhs);
+                Instruction::Binary(dst, op.clone(), lhs, rhs)
+            }
+            CheckedExprKind::IntToFloat(operand) => {
+                let operand = self.lower_expr(operand);
+                Instruction::IntToFloat(dst, operand)
+            }
+            CheckedExprKind::Var(id) if self.variables[*id].global => Instruction::Load(dst, *id),
+            CheckedExprKind::Var(id) => {
+                let src = self.local(*id);
+                if src == dst {
+                    return;
+                }
+                Instruction::Copy(dst, src)
+            }
+            CheckedExprKind::Call(function, args) => {
+                let args: Vec<Reg> = args.iter().map(|arg| self.lower_expr(arg)).collect();
+
+                // print has no meaningful result
+                if function == "print" {
+                    self.emit(Instruction::Print(args[0]));
+                    Instruction::Const(dst, Constant::Int(0))
+                } else {
+                    Instruction::Call(dst, function.clone(), args)
+                }
+            }
+        };
+
+        self.emit(instruction);
+    }
+
+    // Merges the two outcomes of a `&&` or `||` used as a value into one register.
+    fn lower_logical(&mut self, expr: &CheckedExpr, dst: Reg) {
+        let true_id = self.new_block();
+        let false_id = self.new_block();
+        let end_id = self.new_block();
+
+        self.lower_condition(expr, true_id, false_id);
+
+        for (block, value) in [(true_id, true), (false_id, false)] {
+            self.start_block(block);
+            self.emit(Instruction::Const(dst, Constant::Bool(value)));
+            self.terminate(Terminator::Jump(end_id));
+        }
+
+        self.start_block(end_id);
+    }
+}
+
+fn constant(expr: &CheckedExpr) -> Option<Constant> {
+    match &expr.kind {
+        CheckedExprKind::IntegerConstant(i) => Some(Constant::Int(*i)),
+        CheckedExprKind::FloatConstant(f) => Some(Constant::Float(*f)),
+        CheckedExprKind::BoolConstant(b) => Some(Constant::Bool(*b)),
+        CheckedExprKind::QuotedString(s) => Some(Constant::String(s.clone())),
+        _ => None,
+    }
+}
+
+impl fmt::Display for Constant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Constant::Int(i) => write!(f, "{}", i),
+            Constant::Float(x) => write!(f, "{:?}", x),
+            Constant::Bool(b) => write!(f, "{}", b),
+            Constant::String(s) => write!(f, "{:?}", s),
+        }
+    }
+}
+
+// Writes the program in a form like:
+//
+// fn fib(%0: int) -> int {
+// b0:
+//     %2: int = 2
+//     %1: bool = %0 < %2
+//     branch %1, b1, b2
+// b1:
+//     return %0
+// ...
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut globals = false;
+        for (id, variable) in self.variables.iter().enumerate() {
+            if variable.global && variable.used {
+                write!(f, "global {}#{}: {}", variable.name, id, variable.ty)?;
+                match self.initializers.get(&id) {
+                    Some(constant) => writeln!(f, " = {}", constant)?,
+                    None => writeln!(f)?,
+                }
+                globals = true;
+            }
+        }
+
+        for (i, function) in std::iter::once(&self.main)
+            .chain(&self.functions)
+            .enumerate()
+        {
+            if i > 0 || globals {
+                writeln!(f)?;
+            }
+            write_function(f, function, &self.variables)?;
+        }
+        Ok(())
+    }
+}
+
+fn write_function(
+    f: &mut fmt::Formatter<'_>,
+    function: &Function,
+    variables: &[CheckedVariable],
+) -> fmt::Result {
+    let name = |id: &VarId| format!("{}#{}", variables[*id].name, id);
+    let reg = |r: &Reg| format!("%{}", r);
+
+    let params: Vec<String> = function
+        .params
+        .iter()
+        .map(|param| format!("{}: {}", reg(param), function.registers[*param]))
+        .collect();
+    match function.return_type {
+        Some(ty) => writeln!(
+            f,
+            "fn {}({}) -> {} {{",
+            function.name,
+            params.join(", "),
+            ty
+        )?,
+        None => writeln!(f, "program {{")?,
+    }
+
+    for (id, block) in function.blocks.iter().enumerate() {
+        writeln!(f, "b{}:", id)?;
+
+        for instruction in &block.instructions {
+            write!(f, "    ")?;
+            if let Some(dst) = instruction.destination() {
+                write!(f, "{}: {} = ", reg(&dst), function.registers[dst])?;
+            }
+
+            match instruction {
+                Instruction::Const(_, constant) => writeln!(f, "{}", constant)?,
+                Instruction::Copy(_, src) => writeln!(f, "{}", reg(src))?,
+                Instruction::Unary(_, op, operand) => writeln!(f, "{}{}", op, reg(operand))?,
+                Instruction::Binary(_, op, lhs, rhs) => {
+                    writeln!(f, "{} {} {}", reg(lhs), op, reg(rhs))?
+                }
+                Instruction::IntToFloat(_, src) => writeln!(f, "int_to_float {}", reg(src))?,
+                Instruction::Load(_, id) => writeln!(f, "{}", name(id))?,
+                Instruction::Store(id, src) => writeln!(f, "{} = {}", name(id), reg(src))?,
+                Instruction::Call(_, callee, args) => {
+                    let args: Vec<String> = args.iter().map(reg).collect();
+                    writeln!(f, "call {}({})", callee, args.join(", "))?
+                }
+                Instruction::Print(src) => writeln!(f, "print {}", reg(src))?,
+            }
+        }
+
+        match &block.terminator {
+            Terminator::Jump(target) => writeln!(f, "    jump b{}", target)?,
+            Terminator::Branch(condition, then_id, else_id) => writeln!(
+                f,
+                "    branch {}, b{}, b{}",
+                reg(condition),
+                then_id,
+                else_id
+            )?,
+            Terminator::Return(Some(value)) => writeln!(f, "    return {}", reg(value))?,
+            Terminator::Return(None) => writeln!(f, "    return")?,
+            Terminator::Unreachable => writeln!(f, "    unreachable")?,
+        }
+    }
+
+    writeln!(f, "}}")
+}
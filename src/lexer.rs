@@ -13,14 +13,39 @@ pub enum TokenContents {
     Minus,
     Star,
     Slash,
+    Percent,
+    Bang,
+    Ampersand,
+    AmpersandAmpersand,
+    Pipe,
+    PipePipe,
+    Caret,
+    LessLess,
+    GreaterGreater,
 
     Equal,
+    PlusEqual,
+    MinusEqual,
+    StarEqual,
+    SlashEqual,
+    EqualEqual,
+    BangEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
 
     LParen,
     RParen,
+    LBrace,
+    RBrace,
+    Comma,
+    Colon,
+    Arrow,
 
     Integer(i64),
     Float(f64),
+    Bool(bool),
 
     QuotedString(String),
 
@@ -39,6 +64,11 @@ impl Span {
     pub fn new(start: usize, end: usize) -> Self {
         Self { start, end }
     }
+
+    // The span from the start of this one to the end of `other`.
+    pub fn to(self, other: Span) -> Span {
+        Span::new(self.start, other.end)
+    }
 }
 
 impl Token {
@@ -51,23 +81,21 @@ impl Token {
     }
 }
 
-pub fn lex(bytes: &[u8]) -> Result<Vec<Token>, JPLError> {
+// Lexes the whole input, skipping over bad tokens so that every error is reported,
+// up to `max_errors` of them.
+pub fn lex(bytes: &[u8], max_errors: usize) -> Result<Vec<Token>, Vec<JPLError>> {
     let mut tokens = vec![];
+    let mut errors = vec![];
     let mut index = 0;
     let mut line = 1;
 
-    while index < bytes.len() {
+    while index < bytes.len() && errors.len() < max_errors {
         if bytes[index].is_ascii_digit() {
             let start = index;
             let mut floating = false;
             while index < bytes.len() && (bytes[index].is_ascii_digit() || bytes[index] == b'.') {
                 if bytes[index] == b'.' {
                     floating = true;
-                } else if bytes[index] == b'.' && floating {
-                    return Err(JPLError::new(
-                        "Two decimal points in floating point number".to_string(),
-                        line,
-                    ));
                 }
                 index += 1;
             }
@@ -76,10 +104,14 @@ pub fn lex(bytes: &[u8]) -> Result<Vec<Token>, JPLError> {
                 let num = match String::from_utf8_lossy(&bytes[start..index]).parse() {
                     Ok(n) => n,
                     Err(_) => {
-                        return Err(JPLError::new(
-                            "Bad floating point number {}".to_string(),
-                            line,
+                        errors.push(JPLError::new(
+                            format!(
+                                "Bad floating point number {}",
+                                String::from_utf8_lossy(&bytes[start..index])
+                            ),
+                            Span::new(start, index - 1),
                         ));
+                        continue;
                     }
                 };
 
@@ -92,7 +124,11 @@ pub fn lex(bytes: &[u8]) -> Result<Vec<Token>, JPLError> {
                 let num = match String::from_utf8_lossy(&bytes[start..index]).parse() {
                     Ok(n) => n,
                     Err(_) => {
-                        return Err(JPLError::new("Bad integer".to_string(), line));
+                        errors.push(JPLError::new(
+                            "Bad integer".to_string(),
+                            Span::new(start, index - 1),
+                        ));
+                        continue;
                     }
                 };
 
@@ -102,76 +138,262 @@ pub fn lex(bytes: &[u8]) -> Result<Vec<Token>, JPLError> {
                     line,
                 ))
             }
-        } else if bytes[index].is_ascii_alphanumeric() {
+        } else if is_name_byte(bytes[index]) {
             let start = index;
-            while index < bytes.len() && bytes[index].is_ascii_alphanumeric() {
+            while index < bytes.len() && is_name_byte(bytes[index]) {
                 index += 1;
             }
 
-            tokens.push(Token::new(
-                TokenContents::Name(String::from(String::from_utf8_lossy(&bytes[start..index]))),
-                Span::new(start, index - 1),
-                line,
-            ))
+            let contents = match &bytes[start..index] {
+                b"true" => TokenContents::Bool(true),
+                b"false" => TokenContents::Bool(false),
+                name => TokenContents::Name(String::from(String::from_utf8_lossy(name))),
+            };
+
+            tokens.push(Token::new(contents, Span::new(start, index - 1), line))
         } else if bytes[index] == b'"' {
             index += 1;
             let start = index;
 
-            while index < bytes.len() - 1 && bytes[index] != b'"' {
+            while index < bytes.len() && bytes[index] != b'"' {
                 index += 1;
             }
 
-            if bytes[index] != b'"' {
-                return Err(JPLError::new("Unterminated string".to_string(), line));
+            if index == bytes.len() {
+                errors.push(JPLError::new(
+                    "Unterminated string".to_string(),
+                    Span::new(start - 1, index - 1),
+                ));
+                break;
             }
 
+            // the span includes the quotes
             tokens.push(Token::new(
                 TokenContents::QuotedString(String::from(String::from_utf8_lossy(
                     &bytes[start..index],
                 ))),
-                Span::new(start, index - 1),
+                Span::new(start - 1, index),
                 line,
             ));
 
             index += 1;
         } else if bytes[index] == b'+' {
+            if index + 1 < bytes.len() && bytes[index + 1] == b'=' {
+                tokens.push(Token::new(
+                    TokenContents::PlusEqual,
+                    Span::new(index, index + 1),
+                    line,
+                ));
+                index += 2;
+            } else {
+                tokens.push(Token::new(
+                    TokenContents::Plus,
+                    Span::new(index, index),
+                    line,
+                ));
+                index += 1;
+            }
+        } else if bytes[index] == b'-' {
+            if index + 1 < bytes.len() && bytes[index + 1] == b'>' {
+                tokens.push(Token::new(
+                    TokenContents::Arrow,
+                    Span::new(index, index + 1),
+                    line,
+                ));
+                index += 2;
+            } else if index + 1 < bytes.len() && bytes[index + 1] == b'=' {
+                tokens.push(Token::new(
+                    TokenContents::MinusEqual,
+                    Span::new(index, index + 1),
+                    line,
+                ));
+                index += 2;
+            } else {
+                tokens.push(Token::new(
+                    TokenContents::Minus,
+                    Span::new(index, index),
+                    line,
+                ));
+                index += 1;
+            }
+        } else if bytes[index] == b'*' {
+            if index + 1 < bytes.len() && bytes[index + 1] == b'=' {
+                tokens.push(Token::new(
+                    TokenContents::StarEqual,
+                    Span::new(index, index + 1),
+                    line,
+                ));
+                index += 2;
+            } else {
+                tokens.push(Token::new(
+                    TokenContents::Star,
+                    Span::new(index, index),
+                    line,
+                ));
+                index += 1;
+            }
+        } else if bytes[index] == b'=' {
+            if index + 1 < bytes.len() && bytes[index + 1] == b'=' {
+                tokens.push(Token::new(
+                    TokenContents::EqualEqual,
+                    Span::new(index, index + 1),
+                    line,
+                ));
+                index += 2;
+            } else {
+                tokens.push(Token::new(
+                    TokenContents::Equal,
+                    Span::new(index, index),
+                    line,
+                ));
+                index += 1;
+            }
+        } else if bytes[index] == b'!' {
+            if index + 1 < bytes.len() && bytes[index + 1] == b'=' {
+                tokens.push(Token::new(
+                    TokenContents::BangEqual,
+                    Span::new(index, index + 1),
+                    line,
+                ));
+                index += 2;
+            } else {
+                tokens.push(Token::new(
+                    TokenContents::Bang,
+                    Span::new(index, index),
+                    line,
+                ));
+                index += 1;
+            }
+        } else if bytes[index] == b'<' {
+            if index + 1 < bytes.len() && bytes[index + 1] == b'<' {
+                tokens.push(Token::new(
+                    TokenContents::LessLess,
+                    Span::new(index, index + 1),
+                    line,
+                ));
+                index += 2;
+            } else if index + 1 < bytes.len() && bytes[index + 1] == b'=' {
+                tokens.push(Token::new(
+                    TokenContents::LessEqual,
+                    Span::new(index, index + 1),
+                    line,
+                ));
+                index += 2;
+            } else {
+                tokens.push(Token::new(
+                    TokenContents::Less,
+                    Span::new(index, index),
+                    line,
+                ));
+                index += 1;
+            }
+        } else if bytes[index] == b'>' {
+            if index + 1 < bytes.len() && bytes[index + 1] == b'>' {
+                tokens.push(Token::new(
+                    TokenContents::GreaterGreater,
+                    Span::new(index, index + 1),
+                    line,
+                ));
+                index += 2;
+            } else if index + 1 < bytes.len() && bytes[index + 1] == b'=' {
+                tokens.push(Token::new(
+                    TokenContents::GreaterEqual,
+                    Span::new(index, index + 1),
+                    line,
+                ));
+                index += 2;
+            } else {
+                tokens.push(Token::new(
+                    TokenContents::Greater,
+                    Span::new(index, index),
+                    line,
+                ));
+                index += 1;
+            }
+        } else if bytes[index] == b'&' {
+            if index + 1 < bytes.len() && bytes[index + 1] == b'&' {
+                tokens.push(Token::new(
+                    TokenContents::AmpersandAmpersand,
+                    Span::new(index, index + 1),
+                    line,
+                ));
+                index += 2;
+            } else {
+                tokens.push(Token::new(
+                    TokenContents::Ampersand,
+                    Span::new(index, index),
+                    line,
+                ));
+                index += 1;
+            }
+        } else if bytes[index] == b'|' {
+            if index + 1 < bytes.len() && bytes[index + 1] == b'|' {
+                tokens.push(Token::new(
+                    TokenContents::PipePipe,
+                    Span::new(index, index + 1),
+                    line,
+                ));
+                index += 2;
+            } else {
+                tokens.push(Token::new(
+                    TokenContents::Pipe,
+                    Span::new(index, index),
+                    line,
+                ));
+                index += 1;
+            }
+        } else if bytes[index] == b'^' {
             tokens.push(Token::new(
-                TokenContents::Plus,
+                TokenContents::Caret,
                 Span::new(index, index),
                 line,
             ));
             index += 1;
-        } else if bytes[index] == b'-' {
+        } else if bytes[index] == b'%' {
             tokens.push(Token::new(
-                TokenContents::Minus,
+                TokenContents::Percent,
                 Span::new(index, index),
                 line,
             ));
             index += 1;
-        } else if bytes[index] == b'*' {
+        } else if bytes[index] == b'(' {
             tokens.push(Token::new(
-                TokenContents::Star,
+                TokenContents::LParen,
                 Span::new(index, index),
                 line,
             ));
             index += 1;
-        } else if bytes[index] == b'=' {
+        } else if bytes[index] == b')' {
+            tokens.push(Token::new(
+                TokenContents::RParen,
+                Span::new(index, index),
+                line,
+            ));
+            index += 1;
+        } else if bytes[index] == b'{' {
             tokens.push(Token::new(
-                TokenContents::Equal,
+                TokenContents::LBrace,
                 Span::new(index, index),
                 line,
             ));
             index += 1;
-        } else if bytes[index] == b'(' {
+        } else if bytes[index] == b'}' {
             tokens.push(Token::new(
-                TokenContents::LParen,
+                TokenContents::RBrace,
                 Span::new(index, index),
                 line,
             ));
             index += 1;
-        } else if bytes[index] == b')' {
+        } else if bytes[index] == b':' {
             tokens.push(Token::new(
-                TokenContents::RParen,
+                TokenContents::Colon,
+                Span::new(index, index),
+                line,
+            ));
+            index += 1;
+        } else if bytes[index] == b',' {
+            tokens.push(Token::new(
+                TokenContents::Comma,
                 Span::new(index, index),
                 line,
             ));
@@ -194,6 +416,13 @@ pub fn lex(bytes: &[u8]) -> Result<Vec<Token>, JPLError> {
                     line += 1;
                     index += 1;
                 }
+            } else if index + 1 < bytes.len() && bytes[index + 1] == b'=' {
+                tokens.push(Token::new(
+                    TokenContents::SlashEqual,
+                    Span::new(index, index + 1),
+                    line,
+                ));
+                index += 2;
             } else {
                 tokens.push(Token::new(
                     TokenContents::Slash,
@@ -203,13 +432,27 @@ pub fn lex(bytes: &[u8]) -> Result<Vec<Token>, JPLError> {
                 index += 1;
             }
         } else {
-            return Err(JPLError::new(
-                format!("unexpected token '{}'", bytes[index] as char),
-                line,
+            // skip the whole character, along with the continuation bytes of a multi-byte one
+            let start = index;
+            index += 1;
+            while index < bytes.len() && bytes[index] & 0xc0 == 0x80 {
+                index += 1;
+            }
+
+            errors.push(JPLError::new(
+                format!(
+                    "unexpected token '{}'",
+                    String::from_utf8_lossy(&bytes[start..index])
+                ),
+                Span::new(start, index - 1),
             ));
         }
     }
 
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
     tokens.push(Token::new(
         TokenContents::Eof,
         Span::new(index, index),
@@ -218,3 +461,8 @@ pub fn lex(bytes: &[u8]) -> Result<Vec<Token>, JPLError> {
 
     Ok(tokens)
 }
+
+// Digits can only continue a name, as those starting with one are lexed as numbers.
+fn is_name_byte(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || byte == b'_'
+}
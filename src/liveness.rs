@@ -0,0 +1,48 @@
+use std::collections::HashSet;
+
+use crate::ir::{Function, Reg};
+
+// The registers each block may read before writing them, on entry and on exit.
+// Globals aren't tracked, as other functions can read and write them.
+pub struct Liveness {
+    pub live_in: Vec<HashSet<Reg>>,
+    pub live_out: Vec<HashSet<Reg>>,
+}
+
+pub fn analyze(function: &Function) -> Liveness {
+    let blocks = &function.blocks;
+    let mut live_in = vec![HashSet::new(); blocks.len()];
+    let mut live_out = vec![HashSet::new(); blocks.len()];
+
+    // iterate backwards to a fixed point, which for these small functions is quick
+    let mut changed = true;
+    while changed {
+        changed = false;
+
+        for (id, block) in blocks.iter().enumerate().rev() {
+            let out: HashSet<Reg> = block
+                .terminator
+                .successors()
+                .iter()
+                .flat_map(|successor| live_in[*successor].iter().copied())
+                .collect();
+
+            let mut live = out.clone();
+            live.extend(block.terminator.operands());
+            for instruction in block.instructions.iter().rev() {
+                if let Some(reg) = instruction.destination() {
+                    live.remove(&reg);
+                }
+                live.extend(instruction.operands());
+            }
+
+            if live != live_in[id] || out != live_out[id] {
+                live_in[id] = live;
+                live_out[id] = out;
+                changed = true;
+            }
+        }
+    }
+
+    Liveness { live_in, live_out }
+}
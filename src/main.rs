@@ -1,65 +1,209 @@
-use std::{env, fs, process};
+use std::{env, fs, mem, path::PathBuf, process};
 
+use cli::{Command, Options, Stage};
+use lexer::Span;
 use parser::Parser;
+use toolchain::{BuildDir, ToolchainError};
 
-use crate::codegen::compile;
-
+mod asm;
+mod cli;
 mod codegen;
+mod deadcode;
+mod fold;
+mod ir;
 mod lexer;
+mod liveness;
 mod parser;
+mod peephole;
+mod regalloc;
+mod symbols;
+mod toolchain;
+mod typechecker;
 
 #[derive(Debug)]
 pub struct JPLError {
     message: String,
-    line: usize,
+    span: Span,
+    // the file the span points into, filled in by the driver
+    file: String,
 }
 
 impl JPLError {
-    fn new(message: String, line: usize) -> Self {
+    fn new(message: String, span: Span) -> Self {
         Self {
             message,
-            line,
+            span,
+            file: String::new(),
         }
     }
 
-    pub fn print_error(&self) {
-        eprintln!("Error on line {}: {}", self.line, self.message);
+    fn in_file(mut self, file: &str) -> Self {
+        self.file = file.to_string();
+        self
+    }
+
+    pub fn print_error(&self, source: &str) {
+        self.print("error", source);
+    }
+
+    pub fn print_warning(&self, source: &str) {
+        self.print("warning", source);
+    }
+
+    // Prints the message followed by the offending line with the span underlined:
+    //
+    // error: Undefined variable 'x'.
+    //  --> example.jpl:3:7
+    //   |
+    // 3 | print(x + 1)
+    //   |       ^
+    fn print(&self, level: &str, source: &str) {
+        let mut start = self.span.start.min(source.len());
+        if start == source.len() && source.ends_with('\n') {
+            // point just past the last line instead of at an empty one
+            start -= 1;
+        }
+        // spans are byte offsets, which may point into a multi-byte character
+        while !source.is_char_boundary(start) {
+            start -= 1;
+        }
+        let line_start = source[..start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = source[start..]
+            .find('\n')
+            .map_or(source.len(), |i| start + i);
+        let line_number = source[..start].matches('\n').count() + 1;
+        let text = &source[line_start..line_end];
+
+        // keep tabs so the underline lines up with the source
+        let prefix = &source[line_start..start];
+        let padding: String = prefix
+            .chars()
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        let column = prefix.chars().count() + 1;
+        // spans running past the end of the line are cut off there
+        let mut end = (self.span.end + 1)
+            .min(line_end)
+            .max(start + 1)
+            .min(source.len());
+        while !source.is_char_boundary(end) {
+            end += 1;
+        }
+        let width = source[start..end].chars().count();
+        let underline = "^".repeat(width.max(1));
+
+        let gutter = " ".repeat(line_number.to_string().len());
+        eprintln!("{}: {}", level, self.message);
+        eprintln!("{}--> {}:{}:{}", gutter, self.file, line_number, column);
+        eprintln!("{} |", gutter);
+        eprintln!("{} | {}", line_number, text);
+        eprintln!("{} | {}{}", gutter, padding, underline);
     }
 }
 
-fn main() -> Result<(), JPLError> {
-    let args: Vec<String> = env::args().skip(1).collect();
+fn report_errors(errors: Vec<JPLError>, file: &str, source: &str, code: i32) -> ! {
+    let count = errors.len();
+    for error in errors {
+        error.in_file(file).print_error(source);
+        eprintln!();
+    }
+
+    if count == 1 {
+        eprintln!("error: aborting due to previous error");
+    } else {
+        eprintln!("error: aborting due to {} previous errors", count);
+    }
+    process::exit(code);
+}
 
-    if args.len() == 0 {
-        eprintln!("fatal error: no input files");
-        process::exit(1);
+fn report_warnings(warnings: Vec<JPLError>, file: &str, source: &str) {
+    for warning in warnings {
+        warning.in_file(file).print_warning(source);
+        eprintln!();
     }
+}
 
-    let source = match fs::read_to_string(&args[0]) {
+fn main() -> Result<(), JPLError> {
+    let options = cli::parse_args(env::args().skip(1));
+    let path = &options.path;
+
+    let source = match fs::read_to_string(path) {
         Ok(s) => s,
         Err(_) => {
-            eprintln!("failed to read input file {}", args[0]);
+            eprintln!("failed to read input file {}", path);
             process::exit(1);
         }
     };
 
-    let tokens = match lexer::lex(source.as_bytes()) {
+    let tokens = match lexer::lex(source.as_bytes(), options.max_errors) {
         Ok(t) => t,
-        Err(e) => {
-            e.print_error();
-            process::exit(1);
-        }
+        Err(errors) => report_errors(errors, path, &source, 1),
     };
+    if options.command == Command::Emit(Stage::Tokens) {
+        for token in &tokens {
+            println!("{}\t{:?}", token.line, token.contents);
+        }
+        return Ok(());
+    }
 
-    let mut parser = Parser::new(tokens);
-    let statements = match parser.parse() {
+    let mut parser = Parser::new(tokens, options.max_errors);
+    let mut statements = match parser.parse() {
         Ok(_) => parser.statements,
+        Err(errors) => report_errors(errors, path, &source, 2),
+    };
+    if options.command == Command::Emit(Stage::Ast) {
+        println!("{:#?}", statements);
+        return Ok(());
+    }
+
+    if let Err(errors) = fold::fold(&mut statements) {
+        report_errors(errors, path, &source, 3);
+    }
+    let mut program = match typechecker::check(&statements) {
+        Ok(program) => program,
+        Err(e) => report_errors(vec![e], path, &source, 3),
+    };
+    report_warnings(mem::take(&mut program.warnings), path, &source);
+    if options.command == Command::Check {
+        return Ok(());
+    }
+
+    let mut ir = ir::lower(program);
+    deadcode::eliminate(&mut ir);
+    if options.command == Command::Emit(Stage::Ir) {
+        print!("{}", ir);
+        return Ok(());
+    }
+
+    let asm = codegen::compile(&ir, options.peephole);
+    if options.command == Command::Emit(Stage::Asm) {
+        print!("{}", asm);
+        return Ok(());
+    }
+
+    match build_and_run(&asm, &options) {
+        Ok(0) => Ok(()),
+        Ok(code) => process::exit(code),
         Err(e) => {
-            e.print_error();
-            process::exit(2);
+            eprintln!("error: {}", e);
+            process::exit(4);
         }
+    }
+}
+
+// Returns the program's exit code for `jpl run`, and 0 for `jpl build`.
+fn build_and_run(asm: &str, options: &Options) -> Result<i32, ToolchainError> {
+    let build_dir = BuildDir::new(options.save_temps)?;
+    let executable = match (&options.output, options.command) {
+        (Some(output), _) => PathBuf::from(output),
+        (None, Command::Run) => build_dir.file("a.out"),
+        (None, _) => PathBuf::from("a.out"),
     };
-    compile(statements);
 
-    Ok(())
+    toolchain::build(asm, &executable, &build_dir)?;
+    if options.command == Command::Run {
+        return toolchain::run(&executable, &options.program_args);
+    }
+
+    Ok(0)
 }
@@ -1,33 +1,121 @@
+use std::{fmt, mem};
+
 use crate::{
-    lexer::{Token, TokenContents},
+    lexer::{Span, Token, TokenContents},
     JPLError,
 };
 
+// Names that start a statement, and so can't be used as variables
+const STATEMENT_KEYWORDS: [&str; 7] = ["let", "fn", "if", "while", "return", "break", "continue"];
+
 #[derive(Debug)]
 pub struct Parser {
     pub statements: Vec<ParsedStatement>,
     tokens: Vec<Token>,
     idx: usize,
+    loop_depth: usize,
+    // parentheses and argument lists being parsed, inside which a line break never
+    // ends an expression
+    paren_depth: usize,
+    in_function: bool,
+    errors: Vec<JPLError>,
+    max_errors: usize,
 }
 
 #[derive(Debug)]
 pub enum ParsedStatement {
     VarDecl(ParsedVarDecl, ParsedExpr),
-    FunctionCall(String, Vec<ParsedExpr>),
+    // `x = expr`, or `x op= expr` when the operator is present
+    Assignment(String, Option<BinaryOperator>, ParsedExpr, Span),
+    FunctionDecl(ParsedFunction),
+    Return(Option<ParsedExpr>, Span),
+    If(ParsedExpr, ParsedBlock, Option<ParsedBlock>),
+    While(ParsedExpr, ParsedBlock),
+    Block(ParsedBlock),
+    Break,
+    Continue,
+    Expression(ParsedExpr),
 }
 
 #[derive(Debug)]
 pub struct ParsedVarDecl {
     pub name: String,
+    pub span: Span,
+}
+
+#[derive(Debug)]
+pub struct ParsedFunction {
+    pub name: String,
+    pub params: Vec<ParsedParam>,
+    // type names are resolved by the typechecker, None means int
+    pub return_type: Option<String>,
+    pub body: ParsedBlock,
+    // the function's name
+    pub span: Span,
+}
+
+#[derive(Debug)]
+pub struct ParsedParam {
+    pub name: String,
+    pub ty: Option<String>,
+    pub span: Span,
+}
+
+#[derive(Debug)]
+pub struct ParsedBlock {
+    pub statements: Vec<ParsedStatement>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum ParsedExpr {
-    IntegerConstant(i64),
-    FloatConstant(f64),
-    BinaryOp(Box<ParsedExpr>, BinaryOperator, Box<ParsedExpr>),
-    QuotedString(String),
-    Var(String),
+    IntegerConstant(i64, Span),
+    FloatConstant(f64, Span),
+    BoolConstant(bool, Span),
+    UnaryOp(UnaryOperator, Box<ParsedExpr>, Span),
+    BinaryOp(Box<ParsedExpr>, BinaryOperator, Box<ParsedExpr>, Span),
+    QuotedString(String, Span),
+    Var(String, Span),
+    Call(String, Vec<ParsedExpr>, Span),
+}
+
+impl ParsedExpr {
+    pub fn span(&self) -> Span {
+        match self {
+            ParsedExpr::IntegerConstant(_, span)
+            | ParsedExpr::FloatConstant(_, span)
+            | ParsedExpr::BoolConstant(_, span)
+            | ParsedExpr::UnaryOp(_, _, span)
+            | ParsedExpr::BinaryOp(_, _, _, span)
+            | ParsedExpr::QuotedString(_, span)
+            | ParsedExpr::Var(_, span)
+            | ParsedExpr::Call(_, _, span) => *span,
+        }
+    }
+
+    fn contains_call(&self) -> bool {
+        match self {
+            ParsedExpr::Call(..) => true,
+            ParsedExpr::UnaryOp(_, operand, _) => operand.contains_call(),
+            ParsedExpr::BinaryOp(lhs, _, rhs, _) => lhs.contains_call() || rhs.contains_call(),
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum UnaryOperator {
+    Negate,
+    Not,
+}
+
+impl fmt::Display for UnaryOperator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let symbol = match self {
+            UnaryOperator::Negate => "-",
+            UnaryOperator::Not => "!",
+        };
+        write!(f, "{}", symbol)
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -36,42 +124,201 @@ pub enum BinaryOperator {
     Subtract,
     Multiply,
     Divide,
+    Modulo,
+    BitwiseAnd,
+    BitwiseOr,
+    BitwiseXor,
+    ShiftLeft,
+    ShiftRight,
+    LogicalAnd,
+    LogicalOr,
+    Equal,
+    NotEqual,
+    LessThan,
+    LessThanOrEqual,
+    GreaterThan,
+    GreaterThanOrEqual,
+}
+
+impl BinaryOperator {
+    pub fn is_comparison(&self) -> bool {
+        matches!(
+            self,
+            BinaryOperator::Equal
+                | BinaryOperator::NotEqual
+                | BinaryOperator::LessThan
+                | BinaryOperator::LessThanOrEqual
+                | BinaryOperator::GreaterThan
+                | BinaryOperator::GreaterThanOrEqual
+        )
+    }
+
+    pub fn is_logical(&self) -> bool {
+        matches!(self, BinaryOperator::LogicalAnd | BinaryOperator::LogicalOr)
+    }
+
+    // Higher binds tighter. Like Rust, the bitwise operators bind tighter than
+    // comparisons, so `a & 1 == 0` means `(a & 1) == 0`.
+    fn precedence(&self) -> u8 {
+        match self {
+            BinaryOperator::LogicalOr => 1,
+            BinaryOperator::LogicalAnd => 2,
+            BinaryOperator::Equal
+            | BinaryOperator::NotEqual
+            | BinaryOperator::LessThan
+            | BinaryOperator::LessThanOrEqual
+            | BinaryOperator::GreaterThan
+            | BinaryOperator::GreaterThanOrEqual => 3,
+            BinaryOperator::BitwiseOr => 4,
+            BinaryOperator::BitwiseXor => 5,
+            BinaryOperator::BitwiseAnd => 6,
+            BinaryOperator::ShiftLeft | BinaryOperator::ShiftRight => 7,
+            BinaryOperator::Add | BinaryOperator::Subtract => 8,
+            BinaryOperator::Multiply | BinaryOperator::Divide | BinaryOperator::Modulo => 9,
+        }
+    }
+}
+
+impl fmt::Display for BinaryOperator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let symbol = match self {
+            BinaryOperator::Add => "+",
+            BinaryOperator::Subtract => "-",
+            BinaryOperator::Multiply => "*",
+            BinaryOperator::Divide => "/",
+            BinaryOperator::Modulo => "%",
+            BinaryOperator::BitwiseAnd => "&",
+            BinaryOperator::BitwiseOr => "|",
+            BinaryOperator::BitwiseXor => "^",
+            BinaryOperator::ShiftLeft => "<<",
+            BinaryOperator::ShiftRight => ">>",
+            BinaryOperator::LogicalAnd => "&&",
+            BinaryOperator::LogicalOr => "||",
+            BinaryOperator::Equal => "==",
+            BinaryOperator::NotEqual => "!=",
+            BinaryOperator::LessThan => "<",
+            BinaryOperator::LessThanOrEqual => "<=",
+            BinaryOperator::GreaterThan => ">",
+            BinaryOperator::GreaterThanOrEqual => ">=",
+        };
+        write!(f, "{}", symbol)
+    }
 }
 
 impl Parser {
-    pub fn new(tokens: Vec<Token>) -> Self {
+    pub fn new(tokens: Vec<Token>, max_errors: usize) -> Self {
         Self {
             statements: vec![],
             tokens,
             idx: 0,
+            loop_depth: 0,
+            paren_depth: 0,
+            in_function: false,
+            errors: vec![],
+            max_errors,
         }
     }
 
-    pub fn parse(&mut self) -> Result<(), JPLError> {
-        while !self.is_at_end() {
-            self.declaration()?;
+    // Parses the whole file, recovering from syntax errors at statement boundaries
+    // so that each one is reported, up to `max_errors` of them.
+    pub fn parse(&mut self) -> Result<(), Vec<JPLError>> {
+        while !self.is_at_end() && !self.error_limit_reached() {
+            let start = self.idx;
+            let statement = if self.is_keyword("fn") {
+                self.advance();
+                self.function_declaration()
+            } else {
+                self.declaration()
+            };
+
+            match statement {
+                Ok(statement) => self.statements.push(statement),
+                Err(error) => self.recover(error, start),
+            }
         }
 
-        Ok(())
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(mem::take(&mut self.errors))
+        }
     }
 
-    fn declaration(&mut self) -> Result<(), JPLError> {
-        match &self.current().contents {
-            TokenContents::Name(n) => {
-                if n.eq_ignore_ascii_case("let") {
-                    self.advance();
-                    self.var_declaration()?;
-                }
+    fn error_limit_reached(&self) -> bool {
+        self.errors.len() >= self.max_errors
+    }
+
+    // Records an error from the statement starting at token `start` and skips to
+    // where the next statement is likely to begin.
+    fn recover(&mut self, error: JPLError, start: usize) {
+        if !self.error_limit_reached() {
+            self.errors.push(error);
+        }
+
+        // always make progress, even when the statement failed on its first token
+        if self.idx == start {
+            self.advance();
+        }
+        self.synchronize();
+    }
+
+    // Skips tokens until the start of a new line, a statement keyword or the
+    // closing brace of the enclosing block. Blocks opened along the way are skipped
+    // whole, so their closing braces aren't mistaken for the enclosing block's.
+    fn synchronize(&mut self) {
+        let line = self.previous().line;
+
+        while !self.is_at_end() {
+            if self.current().line != line
+                || self.current().contents == TokenContents::RBrace
+                || STATEMENT_KEYWORDS
+                    .iter()
+                    .any(|keyword| self.is_keyword(keyword))
+            {
+                return;
+            }
+
+            if self.current().contents == TokenContents::LBrace {
+                self.skip_block();
+            } else {
+                self.advance();
+            }
+        }
+    }
+
+    fn skip_block(&mut self) {
+        let mut depth = 0;
+        while !self.is_at_end() {
+            match self.advance().contents {
+                TokenContents::LBrace => depth += 1,
+                TokenContents::RBrace => depth -= 1,
+                _ => {}
+            }
+
+            if depth == 0 {
+                return;
             }
-            _ => {}
+        }
+    }
+
+    fn declaration(&mut self) -> Result<ParsedStatement, JPLError> {
+        if self.is_keyword("let") {
+            self.advance();
+            return self.var_declaration();
         }
 
-        self.statement()?;
+        if self.is_keyword("fn") {
+            return Err(JPLError::new(
+                "Functions may only be declared at the top level.".to_string(),
+                self.current().span,
+            ));
+        }
 
-        Ok(())
+        self.statement()
     }
 
-    fn function_call(&mut self) -> Result<(), JPLError> {
+    fn function_declaration(&mut self) -> Result<ParsedStatement, JPLError> {
+        let span = self.current().span;
         let name = match &self.current().contents {
             TokenContents::Name(n) => {
                 let name = n.clone();
@@ -80,174 +327,408 @@ impl Parser {
             }
             _ => Err(JPLError::new(
                 "Expected function name.".to_string(),
-                self.current().line,
+                self.current().span,
             )),
         }?;
 
-        match &self.current().contents {
-            TokenContents::LParen => {
+        self.expect(TokenContents::LParen, "Expected left parenthesis.")?;
+
+        let mut params: Vec<ParsedParam> = vec![];
+        while let TokenContents::Name(n) = &self.current().contents {
+            if params.iter().any(|param| &param.name == n) {
+                return Err(JPLError::new(
+                    format!("Duplicate parameter '{}'.", n),
+                    self.current().span,
+                ));
+            }
+            let name = n.clone();
+            let start = self.advance().span;
+
+            let ty = if self.current().contents == TokenContents::Colon {
                 self.advance();
-                Ok(())
+                Some(self.type_name()?)
+            } else {
+                None
+            };
+            let span = start.to(self.previous().span);
+            params.push(ParsedParam { name, ty, span });
+
+            if self.current().contents != TokenContents::Comma {
+                break;
             }
-            _ => Err(JPLError::new(
-                "Expected left parenthesis.".to_string(),
-                self.current().line,
-            )),
-        }?;
+            self.advance();
+        }
 
-        // TODO: handle more than one argument
-        let mut args = vec![];
+        self.expect(TokenContents::RParen, "Expected right parenthesis.")?;
+
+        let return_type = if self.current().contents == TokenContents::Arrow {
+            self.advance();
+            Some(self.type_name()?)
+        } else {
+            None
+        };
+
+        self.in_function = true;
+        let body = self.block();
+        self.in_function = false;
+
+        Ok(ParsedStatement::FunctionDecl(ParsedFunction {
+            name,
+            params,
+            return_type,
+            body: body?,
+            span,
+        }))
+    }
+
+    fn type_name(&mut self) -> Result<String, JPLError> {
         match &self.current().contents {
-            TokenContents::QuotedString(s) => {
-                args.push(ParsedExpr::QuotedString(s.to_string()));
+            TokenContents::Name(n) => {
+                let name = n.clone();
                 self.advance();
-                Ok(())
+                Ok(name)
             }
-            _ => match self.expression() {
-                Ok(expr) => {
-                    args.push(expr);
-                    Ok(())
-                }
-                Err(_) => Err(JPLError::new(
-                    "Expected expression.".to_string(),
-                    self.current().line,
-                )),
-            },
-        }?;
+            _ => Err(JPLError::new(
+                "Expected type name.".to_string(),
+                self.current().span,
+            )),
+        }
+    }
 
-        match &self.current().contents {
-            TokenContents::RParen => {
+    fn function_call(&mut self) -> Result<ParsedExpr, JPLError> {
+        let start = self.current().span;
+        let name = match &self.current().contents {
+            TokenContents::Name(n) => {
+                let name = n.clone();
                 self.advance();
-                Ok(())
+                Ok(name)
             }
             _ => Err(JPLError::new(
-                "Expected right parenthesis.".to_string(),
-                self.current().line,
+                "Expected function name.".to_string(),
+                self.current().span,
             )),
         }?;
 
-        self.statements
-            .push(ParsedStatement::FunctionCall(name, args));
-        Ok(())
+        self.expect(TokenContents::LParen, "Expected left parenthesis.")?;
+
+        self.paren_depth += 1;
+        let args = self.arguments();
+        self.paren_depth -= 1;
+        let args = args?;
+
+        let end = self.current().span;
+        self.expect(TokenContents::RParen, "Expected right parenthesis.")?;
+
+        Ok(ParsedExpr::Call(name, args, start.to(end)))
+    }
+
+    fn arguments(&mut self) -> Result<Vec<ParsedExpr>, JPLError> {
+        let mut args = vec![];
+        if self.current().contents != TokenContents::RParen {
+            loop {
+                args.push(self.argument()?);
+
+                if self.current().contents != TokenContents::Comma {
+                    break;
+                }
+                self.advance();
+            }
+        }
+        Ok(args)
+    }
+
+    fn argument(&mut self) -> Result<ParsedExpr, JPLError> {
+        self.expression()
+            .map_err(|_| JPLError::new("Expected expression.".to_string(), self.current().span))
     }
 
-    fn var_declaration(&mut self) -> Result<(), JPLError> {
+    fn var_declaration(&mut self) -> Result<ParsedStatement, JPLError> {
+        let span = self.current().span;
         let decl = match &self.current().contents {
             TokenContents::Name(n) => {
                 let name = n.clone();
                 self.advance();
-                Ok(ParsedVarDecl { name })
+                Ok(ParsedVarDecl { name, span })
             }
             _ => Err(JPLError::new(
                 "Expected variable name.".to_string(),
-                self.current().line,
+                self.current().span,
             )),
         }?;
 
-        match &self.current().contents {
-            TokenContents::Equal => {
+        self.expect(TokenContents::Equal, "Expected equals sign.")?;
+
+        let expr = self.expression()?;
+
+        Ok(ParsedStatement::VarDecl(decl, expr))
+    }
+
+    fn if_statement(&mut self) -> Result<ParsedStatement, JPLError> {
+        let condition = self.expression()?;
+        let then_block = self.block()?;
+
+        let else_block = if self.is_keyword("else") {
+            self.advance();
+            if self.is_keyword("if") {
+                // `else if` is sugar for an else block holding a single if statement.
                 self.advance();
-                Ok(())
+                let statements = vec![self.if_statement()?];
+                Some(ParsedBlock { statements })
+            } else {
+                Some(self.block()?)
             }
-            _ => Err(JPLError::new(
-                "Expected equals sign.".to_string(),
-                self.current().line,
-            )),
-        }?;
+        } else {
+            None
+        };
 
-        let expr = self.expression()?;
+        Ok(ParsedStatement::If(condition, then_block, else_block))
+    }
+
+    fn while_statement(&mut self) -> Result<ParsedStatement, JPLError> {
+        let condition = self.expression()?;
+
+        self.loop_depth += 1;
+        let body = self.block();
+        self.loop_depth -= 1;
 
-        self.statements.push(ParsedStatement::VarDecl(decl, expr));
+        Ok(ParsedStatement::While(condition, body?))
+    }
+
+    fn block(&mut self) -> Result<ParsedBlock, JPLError> {
+        self.expect(TokenContents::LBrace, "Expected opening brace.")?;
+
+        let mut statements = vec![];
+        while !matches!(
+            self.current().contents,
+            TokenContents::RBrace | TokenContents::Eof
+        ) && !self.error_limit_reached()
+        {
+            let start = self.idx;
+            match self.declaration() {
+                Ok(statement) => statements.push(statement),
+                Err(error) => self.recover(error, start),
+            }
+        }
+
+        self.expect(TokenContents::RBrace, "Expected closing brace.")?;
 
-        Ok(())
+        Ok(ParsedBlock { statements })
     }
 
-    fn statement(&mut self) -> Result<(), JPLError> {
+    fn statement(&mut self) -> Result<ParsedStatement, JPLError> {
+        if self.is_keyword("if") {
+            self.advance();
+            return self.if_statement();
+        }
+
+        if self.current().contents == TokenContents::LBrace {
+            return Ok(ParsedStatement::Block(self.block()?));
+        }
+
+        if self.is_keyword("return") {
+            if !self.in_function {
+                return Err(JPLError::new(
+                    "'return' outside of a function.".to_string(),
+                    self.current().span,
+                ));
+            }
+
+            let keyword = self.advance();
+            let (line, span) = (keyword.line, keyword.span);
+            // A bare `return` is one followed by the end of the block or of the line.
+            if self.current().line != line
+                || matches!(
+                    self.current().contents,
+                    TokenContents::RBrace | TokenContents::Eof
+                )
+            {
+                return Ok(ParsedStatement::Return(None, span));
+            }
+
+            return Ok(ParsedStatement::Return(Some(self.expression()?), span));
+        }
+
+        if self.is_keyword("while") {
+            self.advance();
+            return self.while_statement();
+        }
+
+        if self.is_keyword("break") || self.is_keyword("continue") {
+            let (statement, keyword) = if self.is_keyword("break") {
+                (ParsedStatement::Break, "break")
+            } else {
+                (ParsedStatement::Continue, "continue")
+            };
+
+            if self.loop_depth == 0 {
+                return Err(JPLError::new(
+                    format!("'{}' outside of a loop.", keyword),
+                    self.current().span,
+                ));
+            }
+
+            self.advance();
+            return Ok(statement);
+        }
+
         match &self.current().contents {
-            TokenContents::Name(_) => {
-                if &self.peek().contents == &TokenContents::LParen {
-                    self.function_call()
-                } else {
-                    Ok(())
+            TokenContents::Name(_) if self.assignment_operator().is_some() => self.assignment(),
+            contents if starts_expression(contents) => {
+                let expr = self.expression()?;
+                // without a call it does nothing, and is likely the rest of an expression
+                // broken up by a line starting with `-`
+                if !expr.contains_call() {
+                    return Err(JPLError::new(
+                        "Expression statement has no effect.".to_string(),
+                        expr.span(),
+                    ));
                 }
-            }
-            TokenContents::Integer(_) | TokenContents::Float(_) => {
-                self.expression()?;
-                Ok(())
+                Ok(ParsedStatement::Expression(expr))
             }
             _ => Err(JPLError::new(
                 "Expected variable or literal.".to_string(),
-                self.current().line,
+                self.current().span,
             )),
         }
     }
 
-    fn expression(&mut self) -> Result<ParsedExpr, JPLError> {
-        let mut lhs = self.term()?;
+    fn assignment(&mut self) -> Result<ParsedStatement, JPLError> {
+        let span = self.current().span;
+        let op = self
+            .assignment_operator()
+            .expect("expected assignment operator");
+        let name = match &self.current().contents {
+            TokenContents::Name(n) => n.clone(),
+            _ => unreachable!(),
+        };
+        self.advance();
+        self.advance();
 
-        while let TokenContents::Plus | TokenContents::Minus = self.current().contents {
-            let op = match self.current().contents {
-                TokenContents::Plus => BinaryOperator::Add,
-                TokenContents::Minus => BinaryOperator::Subtract,
-                _ => unreachable!(),
-            };
-            let rhs = match self.current().contents {
-                TokenContents::Plus | TokenContents::Minus => {
-                    self.advance();
-                    self.term()?
-                }
-                _ => lhs.clone(),
-            };
+        let expr = self.expression()?;
+
+        Ok(ParsedStatement::Assignment(name, op, expr, span))
+    }
 
-            lhs = ParsedExpr::BinaryOp(Box::new(lhs), op, Box::new(rhs))
+    // Looks at the token after the assignment target, Some(None) for a plain `=`.
+    fn assignment_operator(&self) -> Option<Option<BinaryOperator>> {
+        match self.peek().contents {
+            TokenContents::Equal => Some(None),
+            TokenContents::PlusEqual => Some(Some(BinaryOperator::Add)),
+            TokenContents::MinusEqual => Some(Some(BinaryOperator::Subtract)),
+            TokenContents::StarEqual => Some(Some(BinaryOperator::Multiply)),
+            TokenContents::SlashEqual => Some(Some(BinaryOperator::Divide)),
+            _ => None,
         }
+    }
 
-        Ok(lhs)
+    fn expression(&mut self) -> Result<ParsedExpr, JPLError> {
+        self.binary_expression(0)
     }
 
-    fn term(&mut self) -> Result<ParsedExpr, JPLError> {
-        let mut lhs = self.factor()?;
+    // Precedence climbing: parses operands and any operators binding at least as
+    // tightly as `min_precedence`. All binary operators are left associative.
+    fn binary_expression(&mut self, min_precedence: u8) -> Result<ParsedExpr, JPLError> {
+        let mut lhs = self.unary()?;
+
+        while let Some(op) = self.binary_operator() {
+            let precedence = op.precedence();
+            // outside of parentheses, a `-` starting a line starts the next statement
+            // instead, like `-f()`
+            let new_statement = op == BinaryOperator::Subtract
+                && self.paren_depth == 0
+                && self.current().line != self.previous().line;
+            if precedence < min_precedence || new_statement {
+                break;
+            }
 
-        while let TokenContents::Star | TokenContents::Slash = self.current().contents {
-            let op = match self.current().contents {
-                TokenContents::Star => BinaryOperator::Multiply,
-                TokenContents::Slash => BinaryOperator::Divide,
-                _ => unreachable!(),
-            };
-            let rhs = match self.current().contents {
-                TokenContents::Star | TokenContents::Slash => {
-                    self.advance();
-                    self.factor()?
-                }
-                _ => lhs.clone(),
-            };
+            self.advance();
+            let rhs = self.binary_expression(precedence + 1)?;
 
-            lhs = ParsedExpr::BinaryOp(Box::new(lhs), op, Box::new(rhs))
+            let span = lhs.span().to(rhs.span());
+            lhs = ParsedExpr::BinaryOp(Box::new(lhs), op, Box::new(rhs), span)
         }
 
         Ok(lhs)
     }
 
+    fn binary_operator(&self) -> Option<BinaryOperator> {
+        match self.current().contents {
+            TokenContents::Plus => Some(BinaryOperator::Add),
+            TokenContents::Minus => Some(BinaryOperator::Subtract),
+            TokenContents::Star => Some(BinaryOperator::Multiply),
+            TokenContents::Slash => Some(BinaryOperator::Divide),
+            TokenContents::Percent => Some(BinaryOperator::Modulo),
+            TokenContents::Ampersand => Some(BinaryOperator::BitwiseAnd),
+            TokenContents::Pipe => Some(BinaryOperator::BitwiseOr),
+            TokenContents::Caret => Some(BinaryOperator::BitwiseXor),
+            TokenContents::LessLess => Some(BinaryOperator::ShiftLeft),
+            TokenContents::GreaterGreater => Some(BinaryOperator::ShiftRight),
+            TokenContents::AmpersandAmpersand => Some(BinaryOperator::LogicalAnd),
+            TokenContents::PipePipe => Some(BinaryOperator::LogicalOr),
+            TokenContents::EqualEqual => Some(BinaryOperator::Equal),
+            TokenContents::BangEqual => Some(BinaryOperator::NotEqual),
+            TokenContents::Less => Some(BinaryOperator::LessThan),
+            TokenContents::LessEqual => Some(BinaryOperator::LessThanOrEqual),
+            TokenContents::Greater => Some(BinaryOperator::GreaterThan),
+            TokenContents::GreaterEqual => Some(BinaryOperator::GreaterThanOrEqual),
+            _ => None,
+        }
+    }
+
+    fn unary(&mut self) -> Result<ParsedExpr, JPLError> {
+        let op = match self.current().contents {
+            TokenContents::Minus => UnaryOperator::Negate,
+            TokenContents::Bang => UnaryOperator::Not,
+            _ => return self.factor(),
+        };
+        let start = self.advance().span;
+        let operand = self.unary()?;
+
+        let span = start.to(operand.span());
+        Ok(ParsedExpr::UnaryOp(op, Box::new(operand), span))
+    }
+
     fn factor(&mut self) -> Result<ParsedExpr, JPLError> {
-        match &self.advance().contents {
-            TokenContents::Integer(i) => Ok(ParsedExpr::IntegerConstant(*i)),
-            TokenContents::Float(f) => Ok(ParsedExpr::FloatConstant(*f)),
-            TokenContents::Name(s) => Ok(ParsedExpr::Var(s.to_string())),
+        // outside of parentheses, a parenthesis on the next line starts the next statement
+        if matches!(self.current().contents, TokenContents::Name(_))
+            && self.peek().contents == TokenContents::LParen
+            && (self.paren_depth > 0 || self.peek().line == self.current().line)
+        {
+            return self.function_call();
+        }
+
+        // nothing is consumed on error, so recovery can resynchronize on the bad token
+        let token = self.current();
+        let span = token.span;
+        let expr = match &token.contents {
+            TokenContents::Integer(i) => ParsedExpr::IntegerConstant(*i, span),
+            TokenContents::Float(f) => ParsedExpr::FloatConstant(*f, span),
+            TokenContents::Bool(b) => ParsedExpr::BoolConstant(*b, span),
+            TokenContents::QuotedString(s) => ParsedExpr::QuotedString(s.to_string(), span),
+            TokenContents::Name(s)
+                if !STATEMENT_KEYWORDS.iter().any(|k| s.eq_ignore_ascii_case(k)) =>
+            {
+                ParsedExpr::Var(s.to_string(), span)
+            }
             TokenContents::LParen => {
-                let expr = self.expression()?;
-                match &self.advance().contents {
-                    TokenContents::RParen => Ok(expr),
-                    _ => Err(JPLError::new(
-                        "Expected closing parenthesis.".to_string(),
-                        self.current().line,
-                    )),
-                }
+                self.advance();
+                self.paren_depth += 1;
+                let expr = self.expression();
+                self.paren_depth -= 1;
+                let expr = expr?;
+                self.expect(TokenContents::RParen, "Expected closing parenthesis.")?;
+                return Ok(expr);
             }
-            _ => Err(JPLError::new(
-                "Expected parenthesis or number.".to_string(),
-                self.current().line,
-            )),
-        }
+            _ => {
+                return Err(JPLError::new(
+                    "Expected parenthesis or number.".to_string(),
+                    span,
+                ))
+            }
+        };
+
+        self.advance();
+        Ok(expr)
     }
 
     fn current(&self) -> &Token {
@@ -273,9 +754,37 @@ impl Parser {
     }
 
     fn is_at_end(&self) -> bool {
-        match self.current().contents {
-            TokenContents::Eof => true,
+        matches!(self.current().contents, TokenContents::Eof)
+    }
+
+    fn is_keyword(&self, keyword: &str) -> bool {
+        match &self.current().contents {
+            TokenContents::Name(n) => n.eq_ignore_ascii_case(keyword),
             _ => false,
         }
     }
+
+    fn expect(&mut self, contents: TokenContents, message: &str) -> Result<(), JPLError> {
+        if self.current().contents == contents {
+            self.advance();
+            Ok(())
+        } else {
+            Err(JPLError::new(message.to_string(), self.current().span))
+        }
+    }
+}
+
+// Whether the token can start an expression, keep in sync with `unary` and `factor`.
+fn starts_expression(contents: &TokenContents) -> bool {
+    matches!(
+        contents,
+        TokenContents::Minus
+            | TokenContents::Bang
+            | TokenContents::LParen
+            | TokenContents::Name(_)
+            | TokenContents::Integer(_)
+            | TokenContents::Float(_)
+            | TokenContents::Bool(_)
+            | TokenContents::QuotedString(_)
+    )
 }
@@ -0,0 +1,87 @@
+use crate::asm::{Line, Operand};
+
+// Rewrites adjacent pairs of instructions into cheaper equivalents until none apply.
+// Labels sit between the pairs, so nothing is moved across a jump target.
+pub fn optimize(lines: &mut Vec<Line>) {
+    while optimize_pass(lines) {}
+}
+
+fn optimize_pass(lines: &mut Vec<Line>) -> bool {
+    let mut changed = false;
+    let mut output: Vec<Line> = Vec::with_capacity(lines.len());
+
+    for line in lines.drain(..) {
+        let line = match output.last() {
+            Some(previous) => match combine(previous, &line) {
+                Some(replacement) => {
+                    changed = true;
+                    output.pop();
+                    replacement
+                }
+                None => vec![line],
+            },
+            None => vec![line],
+        };
+
+        for line in line {
+            // moving a value onto itself does nothing
+            if let Line::Instruction("mov", operands) = &line {
+                if operands[0] == operands[1] {
+                    changed = true;
+                    continue;
+                }
+            }
+            output.push(line);
+        }
+    }
+
+    *lines = output;
+    changed
+}
+
+// Returns what the two instructions should be replaced with, if anything.
+fn combine(first: &Line, second: &Line) -> Option<Vec<Line>> {
+    let (Line::Instruction(first_mnemonic, first), Line::Instruction(second_mnemonic, second)) =
+        (first, second)
+    else {
+        return None;
+    };
+
+    match (*first_mnemonic, *second_mnemonic) {
+        ("mov", "mov") => combine_moves(first, second),
+        _ => None,
+    }
+}
+
+fn combine_moves(first: &[Operand], second: &[Operand]) -> Option<Vec<Line>> {
+    let (first_dst, first_src) = (&first[0], &first[1]);
+    let (second_dst, second_src) = (&second[0], &second[1]);
+
+    // the first destination is overwritten before it is read
+    if first_dst == second_dst && !operand_uses(second_src, first_dst) {
+        return Some(vec![Line::Instruction("mov", second.to_vec())]);
+    }
+
+    // a value stored to memory and loaded straight back is still in the register,
+    // or is an immediate small enough for the store, so it fits a mov to a register
+    if first_dst.is_memory()
+        && first_dst == second_src
+        && !second_dst.is_memory()
+        && !operand_uses(first_dst, second_dst)
+    {
+        return Some(vec![
+            Line::Instruction("mov", first.to_vec()),
+            Line::Instruction("mov", vec![second_dst.clone(), first_src.clone()]),
+        ]);
+    }
+
+    None
+}
+
+// Whether `operand` depends on the register or memory `location`.
+fn operand_uses(operand: &Operand, location: &Operand) -> bool {
+    match location {
+        Operand::Register(name) => operand.uses(name),
+        _ => operand == location,
+    }
+}
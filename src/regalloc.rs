@@ -0,0 +1,204 @@
+use std::collections::HashMap;
+
+use crate::{
+    asm::{imm, mem, reg, Operand},
+    ir::{Constant, Function, Instruction, Reg},
+    liveness,
+};
+
+// Preserved across calls, by our functions as well as the runtime.
+const CALLEE_SAVED: [&str; 5] = ["rbx", "r12", "r13", "r14", "r15"];
+// Only for values that aren't live across a call. rax, rcx and rdx are kept free as
+// scratch registers, and the argument registers for passing arguments.
+const CALLER_SAVED: [&str; 2] = ["r10", "r11"];
+
+pub struct Allocation {
+    // where each register of the function lives, or the constant it always holds
+    pub locations: HashMap<Reg, Operand>,
+    // callee saved registers the function uses, and where their old values are kept
+    pub saved: Vec<(&'static str, Operand)>,
+    // bytes below rbp for spilled values and saved registers
+    pub frame_size: usize,
+}
+
+// The positions from the first to the last instruction a register is live at.
+struct Interval {
+    reg: Reg,
+    start: usize,
+    end: usize,
+    crosses_call: bool,
+}
+
+// Assigns machine registers to the function's virtual ones by linear scan over their live
+// intervals, spilling the ones that end last to the stack when they run out.
+pub fn allocate(function: &Function) -> Allocation {
+    let mut active: Vec<(Interval, &'static str)> = vec![];
+    let mut free: Vec<&'static str> = CALLER_SAVED.iter().chain(&CALLEE_SAVED).copied().collect();
+    let mut allocation = Allocation {
+        locations: immediates(function),
+        saved: vec![],
+        frame_size: 0,
+    };
+
+    for interval in intervals(function) {
+        if allocation.locations.contains_key(&interval.reg) {
+            continue;
+        }
+
+        active.retain(|(other, register)| {
+            if other.end < interval.start {
+                free.push(register);
+            }
+            other.end >= interval.start
+        });
+
+        let usable = |register: &&str| !interval.crosses_call || CALLEE_SAVED.contains(register);
+        let register = match free.iter().position(usable) {
+            Some(i) => Some(free.remove(i)),
+            None => {
+                let victim = active
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, (_, register))| usable(register))
+                    .max_by_key(|(_, (other, _))| other.end)
+                    .map(|(i, _)| i);
+
+                match victim {
+                    Some(i) if active[i].0.end > interval.end => {
+                        let (other, register) = active.remove(i);
+                        allocation.spill(other.reg);
+                        Some(register)
+                    }
+                    _ => None,
+                }
+            }
+        };
+
+        match register {
+            Some(register) => {
+                allocation.locations.insert(interval.reg, reg(register));
+                active.push((interval, register));
+            }
+            None => allocation.spill(interval.reg),
+        }
+    }
+
+    // the main program exits instead of returning, so it has nothing to preserve
+    if function.return_type.is_some() {
+        for register in CALLEE_SAVED {
+            if allocation
+                .locations
+                .values()
+                .any(|location| *location == reg(register))
+            {
+                let slot = allocation.new_slot();
+                allocation.saved.push((register, slot));
+            }
+        }
+    }
+
+    allocation
+}
+
+impl Allocation {
+    fn new_slot(&mut self) -> Operand {
+        self.frame_size += 8;
+        mem(format!("rbp-{}", self.frame_size))
+    }
+
+    fn spill(&mut self, reg: Reg) {
+        let slot = self.new_slot();
+        self.locations.insert(reg, slot);
+    }
+}
+
+// Registers only ever set to one constant that fits a sign extended 32 bit immediate
+// don't need a machine register, the instructions using them take the constant.
+fn immediates(function: &Function) -> HashMap<Reg, Operand> {
+    let mut definitions: HashMap<Reg, usize> =
+        function.params.iter().map(|param| (*param, 1)).collect();
+    let mut constants = HashMap::new();
+
+    for instruction in function.blocks.iter().flat_map(|block| &block.instructions) {
+        if let Some(dst) = instruction.destination() {
+            *definitions.entry(dst).or_insert(0) += 1;
+        }
+        match instruction {
+            Instruction::Const(dst, Constant::Int(i)) if i32::try_from(*i).is_ok() => {
+                constants.insert(*dst, imm(i));
+            }
+            Instruction::Const(dst, Constant::Bool(b)) => {
+                constants.insert(*dst, imm(*b as i64));
+            }
+            _ => {}
+        }
+    }
+
+    constants.retain(|reg, _| definitions[reg] == 1);
+    constants
+}
+
+// Numbers the instructions in layout order and returns the interval of every register,
+// ordered by where they start. An interval covers its register's whole lifetime, so it
+// may include stretches where the register isn't live.
+//
+// Each instruction reads its operands at an even position and writes its result at
+// the odd one after, so a result can take over the machine register of an operand last
+// read by the same instruction.
+fn intervals(function: &Function) -> Vec<Interval> {
+    let liveness = liveness::analyze(function);
+    let mut ranges: HashMap<Reg, (usize, usize)> = HashMap::new();
+    let mut extend = |reg: Reg, position: usize| {
+        let range = ranges.entry(reg).or_insert((position, position));
+        range.0 = range.0.min(position);
+        range.1 = range.1.max(position);
+    };
+    let mut calls = vec![];
+
+    // parameters are written on entry, before the first instruction
+    for param in &function.params {
+        extend(*param, 1);
+    }
+
+    let mut position = 2;
+    for (id, block) in function.blocks.iter().enumerate() {
+        for reg in &liveness.live_in[id] {
+            extend(*reg, position);
+        }
+
+        for instruction in &block.instructions {
+            for reg in instruction.operands() {
+                extend(reg, position);
+            }
+            if let Some(reg) = instruction.destination() {
+                extend(reg, position + 1);
+            }
+            if matches!(instruction, Instruction::Call(..) | Instruction::Print(_)) {
+                calls.push(position);
+            }
+            position += 2;
+        }
+
+        for reg in block.terminator.operands() {
+            extend(reg, position);
+        }
+        for reg in &liveness.live_out[id] {
+            extend(*reg, position);
+        }
+        position += 2;
+    }
+
+    let mut intervals: Vec<Interval> = ranges
+        .into_iter()
+        .map(|(reg, (start, end))| Interval {
+            reg,
+            start,
+            end,
+            // the call's arguments are read and its result written around it
+            crosses_call: calls.iter().any(|call| start < *call && *call < end),
+        })
+        .collect();
+
+    intervals.sort_by_key(|interval| (interval.start, interval.end, interval.reg));
+    intervals
+}
@@ -0,0 +1,54 @@
+use std::collections::HashMap;
+
+// Index of a variable in CheckedProgram::variables
+pub type VarId = usize;
+
+#[derive(Debug)]
+pub struct SymbolTable {
+    scopes: Vec<HashMap<String, VarId>>,
+}
+
+impl SymbolTable {
+    pub fn new() -> Self {
+        Self {
+            scopes: vec![HashMap::new()],
+        }
+    }
+
+    pub fn push_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    pub fn pop_scope(&mut self) {
+        assert!(self.scopes.len() > 1, "cannot pop the global scope");
+        self.scopes.pop();
+    }
+
+    pub fn is_global_scope(&self) -> bool {
+        self.scopes.len() == 1
+    }
+
+    // Declares a variable in the innermost scope, shadowing any outer variable
+    // with the same name. Redeclarations in the same scope are rejected by the caller.
+    pub fn declare(&mut self, name: &str, id: VarId) {
+        self.scopes
+            .last_mut()
+            .expect("symbol table has no scopes")
+            .insert(name.to_string(), id);
+    }
+
+    pub fn lookup(&self, name: &str) -> Option<VarId> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name).copied())
+    }
+
+    pub fn lookup_current_scope(&self, name: &str) -> Option<VarId> {
+        self.scopes
+            .last()
+            .expect("symbol table has no scopes")
+            .get(name)
+            .copied()
+    }
+}
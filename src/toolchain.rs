@@ -0,0 +1,192 @@
+use std::{
+    collections::hash_map::RandomState,
+    env, fmt, fs,
+    hash::{BuildHasher, Hasher},
+    io,
+    os::unix::{fs::DirBuilderExt, process::ExitStatusExt},
+    path::{Path, PathBuf},
+    process::{self, Command, ExitStatus},
+};
+
+// The runtime the compiled program is linked against, built into the compiler so it
+// can run from any directory.
+const RUNTIME: &str = include_str!("../lib.asm");
+
+// A directory private to this invocation for the intermediate files, so concurrent
+// builds don't clobber each other. It is removed when dropped unless `keep` is set.
+//
+// `jpl run` executes the program from here, so the directory has to be new and only
+// accessible by us, or another user could swap the executable before it runs.
+pub struct BuildDir {
+    path: PathBuf,
+    keep: bool,
+}
+
+impl BuildDir {
+    pub fn new(keep: bool) -> Result<Self, ToolchainError> {
+        let mut attempts = 0;
+        loop {
+            // the name is unpredictable, so nobody can create it in advance
+            let suffix = RandomState::new().build_hasher().finish();
+            let path = env::temp_dir().join(format!("jpl-{}-{:016x}", process::id(), suffix));
+
+            match fs::DirBuilder::new().mode(0o700).create(&path) {
+                Ok(()) => return Ok(Self { path, keep }),
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists && attempts < 100 => {
+                    attempts += 1;
+                }
+                Err(e) => {
+                    return Err(ToolchainError::Io(
+                        format!("failed to create {}", path.display()),
+                        e,
+                    ))
+                }
+            }
+        }
+    }
+
+    pub fn file(&self, name: &str) -> PathBuf {
+        self.path.join(name)
+    }
+}
+
+impl Drop for BuildDir {
+    fn drop(&mut self) {
+        if self.keep {
+            eprintln!("note: intermediate files kept in {}", self.path.display());
+        } else {
+            // failing to clean up shouldn't fail the build
+            let _ = fs::remove_dir_all(&self.path);
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ToolchainError {
+    // nasm or ld isn't installed or isn't on the PATH
+    ToolMissing(&'static str),
+    // the tool's error output
+    AssemblerFailed(String),
+    LinkerFailed(String),
+    Io(String, io::Error),
+}
+
+impl fmt::Display for ToolchainError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ToolchainError::ToolMissing(tool) => {
+                write!(
+                    f,
+                    "could not find `{}`, is it installed and on the PATH?",
+                    tool
+                )
+            }
+            ToolchainError::AssemblerFailed(output) => {
+                write!(f, "assembling failed:\n{}", output.trim_end())
+            }
+            ToolchainError::LinkerFailed(output) => {
+                write!(f, "linking failed:\n{}", output.trim_end())
+            }
+            ToolchainError::Io(context, e) => write!(f, "{}: {}", context, e),
+        }
+    }
+}
+
+// Assembles and links the program with the runtime into the executable `output`.
+pub fn build(asm: &str, output: &Path, dir: &BuildDir) -> Result<(), ToolchainError> {
+    write_asm_file(asm, dir)?;
+    compile_asm_file(dir)?;
+    link_source(output, dir)
+}
+
+// Runs the program, returning its exit code.
+pub fn run(executable: &Path, args: &[String]) -> Result<i32, ToolchainError> {
+    run_source(executable, args)
+}
+
+fn write_asm_file(asm: &str, dir: &BuildDir) -> Result<(), ToolchainError> {
+    for (name, contents) in [("a.asm", asm), ("lib.asm", RUNTIME)] {
+        let path = dir.file(name);
+        fs::write(&path, contents)
+            .map_err(|e| ToolchainError::Io(format!("failed to write {}", path.display()), e))?;
+    }
+    Ok(())
+}
+
+// Runs a tool, returning its error output, which has any warnings on success.
+fn run_tool(tool: &'static str, command: &mut Command) -> Result<(bool, String), ToolchainError> {
+    let output = command.output().map_err(|e| match e.kind() {
+        io::ErrorKind::NotFound => ToolchainError::ToolMissing(tool),
+        _ => ToolchainError::Io(format!("failed to run {}", tool), e),
+    })?;
+
+    // nasm reports errors on stdout
+    let messages = format!(
+        "{}{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+    Ok((output.status.success(), messages))
+}
+
+fn compile_asm_file(dir: &BuildDir) -> Result<(), ToolchainError> {
+    for name in ["a", "lib"] {
+        let (success, messages) = run_tool(
+            "nasm",
+            Command::new("nasm")
+                .arg("-f")
+                .arg("elf64")
+                .arg(dir.file(&format!("{}.asm", name)))
+                .arg("-o")
+                .arg(dir.file(&format!("{}.o", name))),
+        )?;
+
+        if !success {
+            return Err(ToolchainError::AssemblerFailed(messages));
+        }
+        eprint!("{}", messages);
+    }
+
+    Ok(())
+}
+
+fn link_source(output: &Path, dir: &BuildDir) -> Result<(), ToolchainError> {
+    let (success, messages) = run_tool(
+        "ld",
+        Command::new("ld")
+            .arg("-m")
+            .arg("elf_x86_64")
+            .arg(dir.file("a.o"))
+            .arg(dir.file("lib.o"))
+            .arg("-o")
+            .arg(output),
+    )?;
+
+    if !success {
+        return Err(ToolchainError::LinkerFailed(messages));
+    }
+    eprint!("{}", messages);
+
+    Ok(())
+}
+
+// The program inherits the compiler's stdin, stdout and stderr, so its output
+// streams as it runs and it can be used interactively.
+fn run_source(executable: &Path, args: &[String]) -> Result<i32, ToolchainError> {
+    // a bare name would otherwise be looked up in PATH
+    let status = Command::new(Path::new(".").join(executable))
+        .args(args)
+        .status()
+        .map_err(|e| ToolchainError::Io(format!("failed to run {}", executable.display()), e))?;
+
+    Ok(exit_code(status))
+}
+
+// Programs killed by a signal exit like they would from a shell, with 128 + the signal.
+fn exit_code(status: ExitStatus) -> i32 {
+    match (status.code(), status.signal()) {
+        (Some(code), _) => code,
+        (None, Some(signal)) => 128 + signal,
+        (None, None) => 1,
+    }
+}
@@ -0,0 +1,725 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+};
+
+use crate::{
+    lexer::Span,
+    parser::{
+        BinaryOperator, ParsedBlock, ParsedExpr, ParsedFunction, ParsedStatement, UnaryOperator,
+    },
+    symbols::{SymbolTable, VarId},
+    JPLError,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Type {
+    Int,
+    Float,
+    // a pointer to a qword length followed by the bytes
+    String,
+    Bool,
+}
+
+impl Type {
+    fn is_numeric(self) -> bool {
+        matches!(self, Type::Int | Type::Float)
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Type::Int => "int",
+            Type::Float => "float",
+            Type::String => "string",
+            Type::Bool => "bool",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug)]
+pub struct CheckedProgram {
+    pub statements: Vec<CheckedStatement>,
+    pub functions: Vec<CheckedFunction>,
+    pub variables: Vec<CheckedVariable>,
+    // variables that are never read, except those named with a leading underscore
+    pub warnings: Vec<JPLError>,
+}
+
+#[derive(Debug)]
+pub struct CheckedVariable {
+    pub name: String,
+    pub ty: Type,
+    // declared in the top-level scope, rather than in a block or function
+    pub global: bool,
+    pub span: Span,
+    // read anywhere, not counting the read of a compound assignment to it
+    pub used: bool,
+}
+
+#[derive(Debug)]
+pub struct CheckedFunction {
+    pub name: String,
+    pub params: Vec<VarId>,
+    pub return_type: Type,
+    // no return type was declared, so a bare `return` or reaching the end returns 0
+    pub implicit_return: bool,
+    pub body: CheckedBlock,
+}
+
+#[derive(Debug)]
+pub struct CheckedBlock {
+    pub statements: Vec<CheckedStatement>,
+}
+
+#[derive(Debug)]
+pub enum CheckedStatement {
+    VarDecl(VarId, CheckedExpr),
+    // compound assignments are desugared, `x += 1` becomes `x = x + 1`
+    Assignment(VarId, CheckedExpr),
+    Return(Option<CheckedExpr>),
+    If(CheckedExpr, CheckedBlock, Option<CheckedBlock>),
+    While(CheckedExpr, CheckedBlock),
+    Block(CheckedBlock),
+    Break,
+    Continue,
+    Expression(CheckedExpr),
+}
+
+#[derive(Debug, Clone)]
+pub struct CheckedExpr {
+    pub kind: CheckedExprKind,
+    pub ty: Type,
+}
+
+#[derive(Debug, Clone)]
+pub enum CheckedExprKind {
+    IntegerConstant(i64),
+    FloatConstant(f64),
+    BoolConstant(bool),
+    QuotedString(String),
+    UnaryOp(UnaryOperator, Box<CheckedExpr>),
+    BinaryOp(Box<CheckedExpr>, BinaryOperator, Box<CheckedExpr>),
+    IntToFloat(Box<CheckedExpr>),
+    Var(VarId),
+    Call(String, Vec<CheckedExpr>),
+}
+
+struct FunctionSignature {
+    params: Vec<Type>,
+    return_type: Type,
+}
+
+// The globals a function reads or assigns itself, and the functions it calls.
+#[derive(Default)]
+struct GlobalUses {
+    globals: HashSet<VarId>,
+    calls: HashSet<String>,
+}
+
+struct Typechecker {
+    symbols: SymbolTable,
+    variables: Vec<CheckedVariable>,
+    functions: HashMap<String, FunctionSignature>,
+    // return type of the function being checked, None at the top level
+    return_type: Option<Type>,
+    implicit_return: bool,
+    // of the function being checked, None at the top level
+    uses: Option<GlobalUses>,
+    function_uses: HashMap<String, GlobalUses>,
+    // calls made by the top-level code, with the number of variables declared before them
+    top_level_calls: Vec<(String, Span, usize)>,
+}
+
+pub fn check(statements: &[ParsedStatement]) -> Result<CheckedProgram, JPLError> {
+    let mut checker = Typechecker {
+        symbols: SymbolTable::new(),
+        variables: vec![],
+        functions: HashMap::new(),
+        return_type: None,
+        implicit_return: false,
+        uses: None,
+        function_uses: HashMap::new(),
+        top_level_calls: vec![],
+    };
+
+    // functions may be called before their declaration
+    for statement in statements {
+        if let ParsedStatement::FunctionDecl(function) = statement {
+            checker.declare_function(function)?;
+        }
+    }
+
+    let mut checked_statements = vec![];
+    for statement in statements {
+        if !matches!(statement, ParsedStatement::FunctionDecl(_)) {
+            checked_statements.push(checker.check_statement(statement)?);
+        }
+    }
+
+    // function bodies see every global, as they are checked after the top level
+    let mut functions = vec![];
+    for statement in statements {
+        if let ParsedStatement::FunctionDecl(function) = statement {
+            functions.push(checker.check_function(function)?);
+        }
+    }
+
+    // the top level runs in order, so a global used by a function it calls has to be
+    // declared before the call, or the function would see it uninitialized
+    for (function, span, declared) in &checker.top_level_calls {
+        let global = checker
+            .globals_used_by(function)
+            .into_iter()
+            .filter(|id| id >= declared)
+            .min();
+        if let Some(id) = global {
+            return Err(JPLError::new(
+                format!(
+                    "Function '{}' is called before the global '{}' it uses is declared.",
+                    function, checker.variables[id].name
+                ),
+                *span,
+            ));
+        }
+    }
+
+    // function bodies are checked last, so sort to report in source order
+    let mut unused: Vec<&CheckedVariable> = checker
+        .variables
+        .iter()
+        .filter(|variable| !variable.used && !variable.name.starts_with('_'))
+        .collect();
+    unused.sort_by_key(|variable| variable.span.start);
+    let warnings = unused
+        .into_iter()
+        .map(|variable| {
+            JPLError::new(
+                format!("Unused variable '{}'.", variable.name),
+                variable.span,
+            )
+        })
+        .collect();
+
+    Ok(CheckedProgram {
+        statements: checked_statements,
+        functions,
+        variables: checker.variables,
+        warnings,
+    })
+}
+
+fn resolve_type(name: &Option<String>, span: Span) -> Result<Type, JPLError> {
+    match name.as_deref() {
+        None | Some("int") => Ok(Type::Int),
+        Some("float") => Ok(Type::Float),
+        Some("string") => Ok(Type::String),
+        Some("bool") => Ok(Type::Bool),
+        Some(name) => Err(JPLError::new(format!("Unknown type '{}'.", name), span)),
+    }
+}
+
+// Whether running the statements always ends in a `return`. A loop only counts when
+// its condition is always true and nothing breaks out of it.
+fn always_returns(statements: &[CheckedStatement]) -> bool {
+    statements.iter().any(|statement| match statement {
+        CheckedStatement::Return(_) => true,
+        CheckedStatement::If(_, then_block, Some(else_block)) => {
+            always_returns(&then_block.statements) && always_returns(&else_block.statements)
+        }
+        CheckedStatement::While(condition, body) => {
+            matches!(condition.kind, CheckedExprKind::BoolConstant(true))
+                && !breaks(&body.statements)
+        }
+        CheckedStatement::Block(block) => always_returns(&block.statements),
+        _ => false,
+    })
+}
+
+// Whether the statements, as a loop body, can break out of that loop. A `break` in a
+// nested loop leaves the nested one.
+fn breaks(statements: &[CheckedStatement]) -> bool {
+    statements.iter().any(|statement| match statement {
+        CheckedStatement::Break => true,
+        CheckedStatement::If(_, then_block, else_block) => {
+            breaks(&then_block.statements)
+                || else_block
+                    .as_ref()
+                    .is_some_and(|block| breaks(&block.statements))
+        }
+        CheckedStatement::Block(block) => breaks(&block.statements),
+        _ => false,
+    })
+}
+
+// Converts an expression to the expected type, if it is the same or an int promoted to a float.
+fn coerce(expr: CheckedExpr, expected: Type) -> Option<CheckedExpr> {
+    match (expr.ty, expected) {
+        (ty, expected) if ty == expected => Some(expr),
+        (Type::Int, Type::Float) => Some(CheckedExpr {
+            kind: CheckedExprKind::IntToFloat(Box::new(expr)),
+            ty: Type::Float,
+        }),
+        _ => None,
+    }
+}
+
+impl Typechecker {
+    fn declare_function(&mut self, function: &ParsedFunction) -> Result<(), JPLError> {
+        if function.name == "print" || self.functions.contains_key(&function.name) {
+            return Err(JPLError::new(
+                format!("Function '{}' is already defined.", function.name),
+                function.span,
+            ));
+        }
+
+        let mut params = vec![];
+        for param in &function.params {
+            params.push(resolve_type(&param.ty, param.span)?);
+        }
+        let return_type = resolve_type(&function.return_type, function.span)?;
+
+        self.functions.insert(
+            function.name.clone(),
+            FunctionSignature {
+                params,
+                return_type,
+            },
+        );
+        Ok(())
+    }
+
+    fn declare_variable(&mut self, name: &str, ty: Type, span: Span) -> Result<VarId, JPLError> {
+        if self.symbols.lookup_current_scope(name).is_some() {
+            return Err(JPLError::new(
+                format!("Variable '{}' is already declared in this scope.", name),
+                span,
+            ));
+        }
+
+        let id = self.variables.len();
+        self.variables.push(CheckedVariable {
+            name: name.to_string(),
+            ty,
+            global: self.symbols.is_global_scope(),
+            span,
+            used: false,
+        });
+        self.symbols.declare(name, id);
+
+        Ok(id)
+    }
+
+    fn check_function(&mut self, function: &ParsedFunction) -> Result<CheckedFunction, JPLError> {
+        let signature = &self.functions[&function.name];
+        let param_types = signature.params.clone();
+        let return_type = signature.return_type;
+
+        self.symbols.push_scope();
+        let params = function
+            .params
+            .iter()
+            .zip(param_types)
+            .map(|(param, ty)| self.declare_variable(&param.name, ty, param.span))
+            .collect::<Result<_, _>>()?;
+
+        let implicit_return = function.return_type.is_none();
+        self.return_type = Some(return_type);
+        self.implicit_return = implicit_return;
+        self.uses = Some(GlobalUses::default());
+        let body = self.check_block(&function.body)?;
+        self.return_type = None;
+        if !implicit_return && !always_returns(&body.statements) {
+            return Err(JPLError::new(
+                format!(
+                    "Function '{}' does not return a value of type {} on every path.",
+                    function.name, return_type
+                ),
+                function.span,
+            ));
+        }
+        let uses = self.uses.take().expect("function uses are being recorded");
+        self.function_uses.insert(function.name.clone(), uses);
+        self.symbols.pop_scope();
+
+        Ok(CheckedFunction {
+            name: function.name.clone(),
+            params,
+            return_type,
+            implicit_return,
+            body,
+        })
+    }
+
+    // Records a global read or assigned by the function being checked.
+    fn use_global(&mut self, id: VarId) {
+        if let Some(uses) = &mut self.uses {
+            if self.variables[id].global {
+                uses.globals.insert(id);
+            }
+        }
+    }
+
+    // The globals the function uses, itself or through the functions it calls.
+    fn globals_used_by(&self, function: &str) -> HashSet<VarId> {
+        let mut globals = HashSet::new();
+        let mut visited = HashSet::new();
+        let mut pending = vec![function];
+        while let Some(function) = pending.pop() {
+            if !visited.insert(function) {
+                continue;
+            }
+
+            let uses = &self.function_uses[function];
+            globals.extend(&uses.globals);
+            pending.extend(uses.calls.iter().map(String::as_str));
+        }
+        globals
+    }
+
+    fn check_block(&mut self, block: &ParsedBlock) -> Result<CheckedBlock, JPLError> {
+        self.symbols.push_scope();
+
+        let mut statements = vec![];
+        for statement in &block.statements {
+            statements.push(self.check_statement(statement)?);
+        }
+
+        self.symbols.pop_scope();
+
+        Ok(CheckedBlock { statements })
+    }
+
+    fn check_condition(&mut self, condition: &ParsedExpr) -> Result<CheckedExpr, JPLError> {
+        let checked = self.check_expr(condition)?;
+        match checked.ty {
+            Type::Bool => Ok(checked),
+            ty => Err(JPLError::new(
+                format!("Expected a condition of type bool, found {}.", ty),
+                condition.span(),
+            )),
+        }
+    }
+
+    fn check_statement(
+        &mut self,
+        statement: &ParsedStatement,
+    ) -> Result<CheckedStatement, JPLError> {
+        match statement {
+            ParsedStatement::VarDecl(decl, expr) => {
+                // checked first, as it may refer to a variable this declaration shadows
+                let expr = self.check_expr(expr)?;
+                let id = self.declare_variable(&decl.name, expr.ty, decl.span)?;
+
+                Ok(CheckedStatement::VarDecl(id, expr))
+            }
+            ParsedStatement::Assignment(name, op, expr, span) => {
+                let id = match self.symbols.lookup(name) {
+                    Some(id) => id,
+                    None => {
+                        return Err(JPLError::new(
+                            format!("Cannot assign to undeclared variable '{}'.", name),
+                            *span,
+                        ))
+                    }
+                };
+                self.use_global(id);
+
+                let value = match op {
+                    Some(op) => {
+                        let var = CheckedExpr {
+                            kind: CheckedExprKind::Var(id),
+                            ty: self.variables[id].ty,
+                        };
+                        let expr = self.check_expr(expr)?;
+                        self.check_binary_op(var, op, expr, *span)?
+                    }
+                    None => self.check_expr(expr)?,
+                };
+
+                let ty = self.variables[id].ty;
+                let value_ty = value.ty;
+                match coerce(value, ty) {
+                    Some(value) => Ok(CheckedStatement::Assignment(id, value)),
+                    None => Err(JPLError::new(
+                        format!(
+                            "Cannot assign a value of type {} to variable '{}' of type {}.",
+                            value_ty, name, ty
+                        ),
+                        *span,
+                    )),
+                }
+            }
+            ParsedStatement::FunctionDecl(_) => {
+                unreachable!("functions are only declared at the top level")
+            }
+            ParsedStatement::Return(expr, span) => {
+                let return_type = self.return_type.expect("return outside of function");
+                let expr = match expr {
+                    Some(expr) => {
+                        let checked = self.check_expr(expr)?;
+                        let ty = checked.ty;
+                        match coerce(checked, return_type) {
+                            Some(checked) => Some(checked),
+                            None => {
+                                return Err(JPLError::new(
+                                    format!(
+                                        "Expected a return value of type {}, found {}.",
+                                        return_type, ty
+                                    ),
+                                    span.to(expr.span()),
+                                ))
+                            }
+                        }
+                    }
+                    None if self.implicit_return => None,
+                    None => {
+                        return Err(JPLError::new(
+                            format!("Expected a return value of type {}.", return_type),
+                            *span,
+                        ))
+                    }
+                };
+
+                Ok(CheckedStatement::Return(expr))
+            }
+            ParsedStatement::If(condition, then_block, else_block) => {
+                let condition = self.check_condition(condition)?;
+                let then_block = self.check_block(then_block)?;
+                let else_block = match else_block {
+                    Some(block) => Some(self.check_block(block)?),
+                    None => None,
+                };
+
+                Ok(CheckedStatement::If(condition, then_block, else_block))
+            }
+            ParsedStatement::While(condition, body) => {
+                let condition = self.check_condition(condition)?;
+                let body = self.check_block(body)?;
+
+                Ok(CheckedStatement::While(condition, body))
+            }
+            ParsedStatement::Block(block) => Ok(CheckedStatement::Block(self.check_block(block)?)),
+            ParsedStatement::Break => Ok(CheckedStatement::Break),
+            ParsedStatement::Continue => Ok(CheckedStatement::Continue),
+            ParsedStatement::Expression(expr) => {
+                Ok(CheckedStatement::Expression(self.check_expr(expr)?))
+            }
+        }
+    }
+
+    fn check_binary_op(
+        &mut self,
+        lhs: CheckedExpr,
+        op: &BinaryOperator,
+        rhs: CheckedExpr,
+        span: Span,
+    ) -> Result<CheckedExpr, JPLError> {
+        let error = JPLError::new(
+            format!(
+                "Operator '{}' cannot be applied to {} and {}.",
+                op, lhs.ty, rhs.ty
+            ),
+            span,
+        );
+
+        let operand_type = match op {
+            BinaryOperator::LogicalAnd | BinaryOperator::LogicalOr => {
+                (lhs.ty == Type::Bool && rhs.ty == Type::Bool).then_some(Type::Bool)
+            }
+            // on bools these are the logical operators without short-circuiting
+            BinaryOperator::BitwiseAnd | BinaryOperator::BitwiseOr | BinaryOperator::BitwiseXor => {
+                (lhs.ty == rhs.ty && matches!(lhs.ty, Type::Int | Type::Bool)).then_some(lhs.ty)
+            }
+            BinaryOperator::Modulo | BinaryOperator::ShiftLeft | BinaryOperator::ShiftRight => {
+                (lhs.ty == Type::Int && rhs.ty == Type::Int).then_some(Type::Int)
+            }
+            _ if lhs.ty.is_numeric() && rhs.ty.is_numeric() => {
+                // mixed arithmetic is done in floating point
+                if lhs.ty == Type::Float || rhs.ty == Type::Float {
+                    Some(Type::Float)
+                } else {
+                    Some(Type::Int)
+                }
+            }
+            BinaryOperator::Equal | BinaryOperator::NotEqual
+                if lhs.ty == Type::Bool && rhs.ty == Type::Bool =>
+            {
+                Some(Type::Bool)
+            }
+            _ => None,
+        };
+
+        let operand_type = match operand_type {
+            Some(ty) => ty,
+            None => return Err(error),
+        };
+        let lhs = coerce(lhs, operand_type).unwrap();
+        let rhs = coerce(rhs, operand_type).unwrap();
+
+        let ty = if op.is_comparison() {
+            Type::Bool
+        } else {
+            operand_type
+        };
+
+        Ok(CheckedExpr {
+            kind: CheckedExprKind::BinaryOp(Box::new(lhs), op.clone(), Box::new(rhs)),
+            ty,
+        })
+    }
+
+    fn check_unary_op(
+        &mut self,
+        op: &UnaryOperator,
+        operand: CheckedExpr,
+        span: Span,
+    ) -> Result<CheckedExpr, JPLError> {
+        let valid = match op {
+            UnaryOperator::Negate => operand.ty.is_numeric(),
+            // bitwise not on ints
+            UnaryOperator::Not => matches!(operand.ty, Type::Int | Type::Bool),
+        };
+
+        if !valid {
+            return Err(JPLError::new(
+                format!("Operator '{}' cannot be applied to {}.", op, operand.ty),
+                span,
+            ));
+        }
+
+        let ty = operand.ty;
+        Ok(CheckedExpr {
+            kind: CheckedExprKind::UnaryOp(op.clone(), Box::new(operand)),
+            ty,
+        })
+    }
+
+    fn check_call(
+        &mut self,
+        function: &str,
+        args: &[ParsedExpr],
+        span: Span,
+    ) -> Result<CheckedExpr, JPLError> {
+        // print accepts a value of any type
+        if function == "print" {
+            if args.len() != 1 {
+                return Err(JPLError::new(
+                    "Function 'print' expects 1 argument.".to_string(),
+                    span,
+                ));
+            }
+
+            return Ok(CheckedExpr {
+                kind: CheckedExprKind::Call(function.to_string(), vec![self.check_expr(&args[0])?]),
+                ty: Type::Int,
+            });
+        }
+
+        let (params, return_type) = match self.functions.get(function) {
+            Some(signature) => (signature.params.clone(), signature.return_type),
+            None => {
+                return Err(JPLError::new(
+                    format!("Undefined function '{}'.", function),
+                    span,
+                ))
+            }
+        };
+
+        match &mut self.uses {
+            Some(uses) => {
+                uses.calls.insert(function.to_string());
+            }
+            None => {
+                let declared = self.variables.len();
+                self.top_level_calls
+                    .push((function.to_string(), span, declared));
+            }
+        }
+
+        if args.len() != params.len() {
+            return Err(JPLError::new(
+                format!(
+                    "Function '{}' expects {} arguments but {} were given.",
+                    function,
+                    params.len(),
+                    args.len()
+                ),
+                span,
+            ));
+        }
+
+        let mut checked_args = vec![];
+        for (i, (arg, ty)) in args.iter().zip(params).enumerate() {
+            let checked = self.check_expr(arg)?;
+            let arg_ty = checked.ty;
+            match coerce(checked, ty) {
+                Some(checked) => checked_args.push(checked),
+                None => {
+                    return Err(JPLError::new(
+                        format!(
+                            "Argument {} of '{}' must be {}, found {}.",
+                            i + 1,
+                            function,
+                            ty,
+                            arg_ty
+                        ),
+                        arg.span(),
+                    ))
+                }
+            }
+        }
+
+        Ok(CheckedExpr {
+            kind: CheckedExprKind::Call(function.to_string(), checked_args),
+            ty: return_type,
+        })
+    }
+
+    fn check_expr(&mut self, expr: &ParsedExpr) -> Result<CheckedExpr, JPLError> {
+        match expr {
+            ParsedExpr::IntegerConstant(i, _) => Ok(CheckedExpr {
+                kind: CheckedExprKind::IntegerConstant(*i),
+                ty: Type::Int,
+            }),
+            ParsedExpr::FloatConstant(f, _) => Ok(CheckedExpr {
+                kind: CheckedExprKind::FloatConstant(*f),
+                ty: Type::Float,
+            }),
+            ParsedExpr::BoolConstant(b, _) => Ok(CheckedExpr {
+                kind: CheckedExprKind::BoolConstant(*b),
+                ty: Type::Bool,
+            }),
+            ParsedExpr::QuotedString(s, _) => Ok(CheckedExpr {
+                kind: CheckedExprKind::QuotedString(s.clone()),
+                ty: Type::String,
+            }),
+            ParsedExpr::UnaryOp(op, operand, span) => {
+                let operand = self.check_expr(operand)?;
+                self.check_unary_op(op, operand, *span)
+            }
+            ParsedExpr::BinaryOp(lhs, op, rhs, span) => {
+                let lhs = self.check_expr(lhs)?;
+                let rhs = self.check_expr(rhs)?;
+                self.check_binary_op(lhs, op, rhs, *span)
+            }
+            ParsedExpr::Var(name, span) => match self.symbols.lookup(name) {
+                Some(id) => {
+                    self.variables[id].used = true;
+                    self.use_global(id);
+                    Ok(CheckedExpr {
+                        kind: CheckedExprKind::Var(id),
+                        ty: self.variables[id].ty,
+                    })
+                }
+                None => Err(JPLError::new(
+                    format!("Undefined variable '{}'.", name),
+                    *span,
+                )),
+            },
+            ParsedExpr::Call(function, args, span) => self.check_call(function, args, *span),
+        }
+    }
+}